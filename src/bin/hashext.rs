@@ -0,0 +1,87 @@
+extern crate cryptopals_rs;
+
+use std::env;
+use std::io::{self, Write};
+use std::process;
+
+use cryptopals_rs::encoding::{FromHex, ToHex};
+
+use cryptopals_rs::lengthext::{forge, url_escape, Algorithm};
+
+const USAGE: &'static str = "\
+usage: hashext <sha1|md4> <message> <mac> <append> [options]
+
+Forge H(key || message || padding || append) from the hex <mac> of
+H(key || message), once per guessed key length.
+
+options:
+    --keylen N|MIN-MAX   key lengths to try (default 1-64)
+    --hex-input          <message> and <append> are hex encoded
+    --url                print messages URL-escaped instead of hex";
+
+fn fail(msg: &str) -> ! {
+    let _ = writeln!(io::stderr(), "hashext: {}\n\n{}", msg, USAGE);
+    process::exit(1);
+}
+
+fn parse_range(s: &str) -> Option<(usize, usize)> {
+    let mut parts = s.splitn(2, '-');
+    let lo = parts.next().and_then(|x| x.parse().ok());
+    let hi = match parts.next() {
+        Some(x) => x.parse().ok(),
+        None => lo,
+    };
+    match (lo, hi) {
+        (Some(lo), Some(hi)) if lo <= hi => Some((lo, hi)),
+        _ => None,
+    }
+}
+
+fn main() {
+    let mut positional = Vec::new();
+    let mut key_lens = (1, 64);
+    let mut hex_input = false;
+    let mut url = false;
+
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match &arg[..] {
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                return;
+            },
+            "--keylen" => {
+                key_lens = args.next()
+                    .and_then(|s| parse_range(&s))
+                    .unwrap_or_else(|| fail("--keylen expects N or MIN-MAX"));
+            },
+            "--hex-input" => hex_input = true,
+            "--url" => url = true,
+            _ => positional.push(arg),
+        }
+    }
+    if positional.len() != 4 {
+        fail("expected 4 arguments");
+    }
+
+    let alg: Algorithm = positional[0].parse().unwrap_or_else(|e: String| fail(&e));
+    let decode = |s: &str| if hex_input {
+        s.from_hex().unwrap_or_else(|_| fail("invalid hex input"))
+    } else {
+        s.as_bytes().to_vec()
+    };
+    let msg = decode(&positional[1]);
+    let append = decode(&positional[3]);
+    let mac = positional[2].from_hex().unwrap_or_else(|_| fail("<mac> must be hex"));
+    if mac.len() != alg.mac_bytes() {
+        fail(&format!("{:?} MACs are {} bytes", alg, alg.mac_bytes()));
+    }
+
+    for key_len in key_lens.0..key_lens.1 + 1 {
+        let f = forge(alg, &msg, &mac, &append, key_len);
+        let message = if url { url_escape(&f.message) } else { f.message.to_hex() };
+        println!("keylen {}", key_len);
+        println!("  mac     {}", f.mac.to_hex());
+        println!("  message {}", message);
+    }
+}
@@ -1,8 +1,8 @@
 #![allow(dead_code)]
 
+extern crate cryptopals_rs;
 extern crate hyper;
 extern crate rand;
-extern crate rustc_serialize as serialize;
 
 //use std::f32;
 use std::sync::atomic::{AtomicBool, Ordering};
@@ -10,56 +10,28 @@ use std::sync::{Arc, Mutex, mpsc};
 use std::time::Instant;
 use std::thread;
 
+use cryptopals_rs::gf2::{BitMatrix, BitVec};
 use hyper::client::Client;
 use hyper::status::StatusCode;
 use rand::{Rng, weak_rng};
-use serialize::hex::ToHex;
-
-// Generate some full-rank byte permutation matrices in Python
-//
-// >>> import random
-// >>> U = [i for i in range(256)]
-// >>> def count_bits(x):
-// ...   count = 0
-// ...   for i in range(8):
-// ...     count += (x & (1 << i)) >> i
-// ...   return count
-// ...
-// >>> def dot(A, x):
-// ...   y = 0
-// ...   for i in range(8):
-// ...     y |= (count_bits(A[7 - i] & x) & 1) << i
-// ...   return y
-// ...
-// >>> for j in range(10):
-// ...   A = random.sample(U, 8)
-// ...   while len(set([dot(A, i) for i in range(256)])) != 256:
-// ...     A = random.sample(U, 8)
-// ...   print(', '.join("0x%02x" % a for a in A) + ',')
-// ...
+use cryptopals_rs::encoding::ToHex;
+
 const N_MATS: usize = 10;
-const N_ROWS: usize = 8 * N_MATS;
-const PERM_MATS: [u8; N_ROWS] = [
-    0x5d, 0x6b, 0x62, 0x95, 0xdd, 0x5e, 0xb9, 0xd3,
-    0xce, 0x92, 0x68, 0x93, 0x33, 0x8a, 0x86, 0xcd,
-    0x56, 0x0a, 0xc9, 0x4b, 0xee, 0x65, 0xbf, 0x2c,
-    0x8e, 0x52, 0x5f, 0x4d, 0x64, 0x79, 0xa1, 0x55,
-    0xca, 0x6f, 0x22, 0x61, 0x47, 0xa7, 0x79, 0xd7,
-    0x49, 0x03, 0x66, 0x74, 0xbc, 0x7d, 0xcd, 0x61,
-    0x17, 0x09, 0x81, 0xa0, 0x9e, 0x05, 0x99, 0x7b,
-    0xe0, 0xce, 0x1f, 0x68, 0x7f, 0xc4, 0x2d, 0x1a,
-    0xf1, 0xaf, 0xad, 0xd4, 0xf7, 0x70, 0x4c, 0x75,
-    0x47, 0x12, 0xf2, 0x84, 0x89, 0x1a, 0x04, 0x3f,
-];
+
+// random full-rank 8x8 matrices, each a permutation of the byte values
+fn perm_mats<R: Rng>(rng: &mut R) -> Vec<BitMatrix> {
+    (0..N_MATS).map(|_| loop {
+        let a = BitMatrix::random(rng, 8, 8);
+        if a.rank() == 8 {
+            return a;
+        }
+    }).collect()
+}
 
 // byte matrix-vector product
 #[allow(non_snake_case)]
-fn dot(A: &[u8], x: u8) -> u8 {
-    let mut y = 0_u8;
-    for i in 0..8 {
-        y |= ((A[7 - i] & x).count_ones() as u8 & 1) << i;
-    }
-    y
+fn dot(A: &BitMatrix, x: u8) -> u8 {
+    A.mul_vec(&BitVec::from_u64(x as u64, 8)).words()[0] as u8
 }
 
 fn argmax<T: PartialOrd>(xs: &[T]) -> Option<usize> {
@@ -144,13 +116,14 @@ fn main() {
     }).collect::<Vec<_>>();
 
     let mut rng = weak_rng();
+    let perm_mats = perm_mats(&mut rng);
 
     for i in 0..20 {
         let repeats = 150/(i+1) + 45;
 
         for _ in 0..repeats {
             let j = rng.gen_range(0, N_MATS);
-            let A = &PERM_MATS[8*j..8*(j+1)];
+            let A = &perm_mats[j];
             
             for b_ in 0..255+1 {
                 let b = b_ as u8;
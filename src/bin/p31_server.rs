@@ -5,7 +5,6 @@ extern crate cryptopals_rs;
 extern crate iron;
 extern crate logger;
 extern crate rand;
-extern crate rustc_serialize as serialize;
 
 use std::collections::HashMap;
 //use std::fs::File;
@@ -21,7 +20,7 @@ use iron::{BeforeMiddleware, typemap, status};
 //use logger::Logger;
 use rand::{Rng, weak_rng};
 use rust_crypto::digest::Digest;
-use serialize::hex::{FromHex, ToHex};
+use cryptopals_rs::encoding::{FromHex, ToHex};
 
 use cryptopals_rs::sha1::Sha1;
 
@@ -0,0 +1,486 @@
+use std::fmt;
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+
+use rand::Rng;
+use rust_crypto::digest::Digest;
+use encoding::{FromHex, ToHex};
+
+use drbg::{Drbg, Mechanism};
+
+/// Parser for NIST CAVP response (`.rsp`) files.
+///
+/// A response file is a sequence of sections, each introduced by one or more
+/// bracketed parameter lines like `[L = 20]` or `[ENCRYPT]`, followed by
+/// records of `NAME = value` lines separated by blank lines. Lines starting
+/// with `#` are comments.
+pub struct Section {
+    pub params: Vec<(String, String)>,
+    pub records: Vec<Record>,
+}
+
+pub struct Record {
+    pub line: usize,
+    pub fields: Vec<(String, String)>,
+}
+
+#[derive(Debug)]
+pub struct ParseError {
+    pub line: usize,
+    pub msg: String,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.msg)
+    }
+}
+
+fn split_assignment(s: &str) -> (String, String) {
+    match s.find('=') {
+        Some(idx) => (s[..idx].trim().to_string(), s[idx + 1..].trim().to_string()),
+        None => (s.trim().to_string(), String::new()),
+    }
+}
+
+impl Section {
+    /// Look up a bracketed parameter, e.g. `param("L") == Some("20")`. Flag
+    /// parameters like `[ENCRYPT]` have an empty value.
+    pub fn param(&self, key: &str) -> Option<&str> {
+        self.params.iter()
+            .find(|&&(ref k, _)| k == key)
+            .map(|&(_, ref v)| v.as_str())
+    }
+
+    pub fn has_param(&self, key: &str) -> bool {
+        self.param(key).is_some()
+    }
+}
+
+impl Record {
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.fields.iter()
+            .find(|&&(ref k, _)| k == key)
+            .map(|&(_, ref v)| v.as_str())
+    }
+
+    /// All values of a repeated field, in file order.
+    pub fn get_all(&self, key: &str) -> Vec<&str> {
+        self.fields.iter()
+            .filter(|&&(ref k, _)| k == key)
+            .map(|&(_, ref v)| v.as_str())
+            .collect()
+    }
+
+    pub fn hex(&self, key: &str) -> Option<Vec<u8>> {
+        self.get(key).and_then(|v| v.from_hex().ok())
+    }
+
+    pub fn int(&self, key: &str) -> Option<u64> {
+        self.get(key).and_then(|v| v.parse().ok())
+    }
+
+    /// A short identifier for failure reports.
+    pub fn name(&self) -> String {
+        match self.get("COUNT").or(self.get("Len")) {
+            Some(id) => format!("record at line {} ({})", self.line, id),
+            None => format!("record at line {}", self.line),
+        }
+    }
+}
+
+pub fn parse(input: &str) -> Result<Vec<Section>, ParseError> {
+    let mut sections: Vec<Section> = Vec::new();
+    let mut record: Option<Record> = None;
+    let mut in_params = false;
+
+    for (i, raw) in input.lines().enumerate() {
+        let lineno = i + 1;
+        let line = raw.trim();
+
+        if line.is_empty() || line.starts_with('#') {
+            if let Some(r) = record.take() {
+                if sections.is_empty() {
+                    sections.push(Section { params: Vec::new(), records: Vec::new() });
+                }
+                sections.last_mut().unwrap().records.push(r);
+            }
+            continue;
+        }
+
+        if line.starts_with('[') {
+            if !line.ends_with(']') {
+                return Err(ParseError { line: lineno, msg: "unterminated section header".to_string() });
+            }
+            if let Some(r) = record.take() {
+                if sections.is_empty() {
+                    sections.push(Section { params: Vec::new(), records: Vec::new() });
+                }
+                sections.last_mut().unwrap().records.push(r);
+            }
+            if !in_params {
+                sections.push(Section { params: Vec::new(), records: Vec::new() });
+                in_params = true;
+            }
+            let param = split_assignment(&line[1..line.len() - 1]);
+            sections.last_mut().unwrap().params.push(param);
+            continue;
+        }
+
+        if line.find('=').is_none() {
+            return Err(ParseError { line: lineno, msg: format!("expected `NAME = value`, got {:?}", line) });
+        }
+        in_params = false;
+        record.get_or_insert(Record { line: lineno, fields: Vec::new() })
+            .fields
+            .push(split_assignment(line));
+    }
+
+    if let Some(r) = record.take() {
+        if sections.is_empty() {
+            sections.push(Section { params: Vec::new(), records: Vec::new() });
+        }
+        sections.last_mut().unwrap().records.push(r);
+    }
+
+    Ok(sections)
+}
+
+pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Vec<Section>> {
+    let mut f = try!(File::open(path));
+    let mut s = String::new();
+    try!(f.read_to_string(&mut s));
+    parse(&s).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))
+}
+
+/// Outcome of running a response file against an implementation.
+#[derive(Debug, Default)]
+pub struct Report {
+    pub passed: usize,
+    pub failed: Vec<String>,
+}
+
+impl Report {
+    fn check(&mut self, ok: bool, record: &Record) {
+        if ok {
+            self.passed += 1;
+        } else {
+            self.failed.push(record.name());
+        }
+    }
+
+    /// Like `check`, with `detail` added to the failure entry.
+    fn check_detail<F: FnOnce() -> String>(&mut self, ok: bool, record: &Record, detail: F) {
+        if ok {
+            self.passed += 1;
+        } else {
+            self.failed.push(format!("{}: {}", record.name(), detail()));
+        }
+    }
+}
+
+fn digest_bytes<D: Digest>(d: &mut D, msg: &[u8]) -> Vec<u8> {
+    let mut out = vec![0_u8; d.output_bytes()];
+    d.reset();
+    d.input(msg);
+    d.result(&mut out);
+    out
+}
+
+/// Byte oriented `ShortMsg`/`LongMsg` files: `Len` (in bits), `Msg`, `MD`.
+pub fn run_hash_msg<D: Digest>(sections: &[Section], d: &mut D) -> Report {
+    let mut report = Report::default();
+    for record in sections.iter().flat_map(|s| s.records.iter()) {
+        let (len, msg, md) = match (record.int("Len"), record.hex("Msg"), record.hex("MD")) {
+            (Some(len), Some(msg), Some(md)) => (len, msg, md),
+            _ => continue,
+        };
+        if len % 8 != 0 || (len / 8) as usize > msg.len() {
+            report.failed.push(record.name());
+            continue;
+        }
+        let out = digest_bytes(d, &msg[..(len / 8) as usize]);
+        report.check(out == md, record);
+    }
+    report
+}
+
+/// `Monte` files: a `Seed` record followed by 100 checkpoints, each the
+/// result of 1000 chained iterations of `MD_i = H(MD_{i-3} || MD_{i-2} ||
+/// MD_{i-1})`.
+pub fn run_hash_monte<D: Digest>(sections: &[Section], d: &mut D) -> Report {
+    let mut report = Report::default();
+    let mut seed: Option<Vec<u8>> = None;
+    for record in sections.iter().flat_map(|s| s.records.iter()) {
+        if let Some(s) = record.hex("Seed") {
+            seed = Some(s);
+            continue;
+        }
+        let (expected, s) = match (record.hex("MD"), seed.take()) {
+            (Some(md), Some(s)) => (md, s),
+            _ => continue,
+        };
+
+        let mut md = vec![s.clone(), s.clone(), s];
+        for _ in 3..1003 {
+            let mut m = Vec::with_capacity(3 * expected.len());
+            for prev in &md[md.len() - 3..] {
+                m.extend_from_slice(prev);
+            }
+            let next = digest_bytes(d, &m);
+            md.remove(0);
+            md.push(next);
+        }
+        let out = md.pop().unwrap();
+
+        report.check(out == expected, record);
+        seed = Some(out);
+    }
+    report
+}
+
+/// Block cipher mode files with `[ENCRYPT]`/`[DECRYPT]` sections of `KEY`,
+/// optional `IV`, `PLAINTEXT` and `CIPHERTEXT`. Both closures take
+/// `(key, iv, input)`; `iv` is empty for modes that don't use one.
+pub fn run_cipher<E, D>(sections: &[Section], encrypt: E, decrypt: D) -> Report
+    where E: Fn(&[u8], &[u8], &[u8]) -> Vec<u8>,
+          D: Fn(&[u8], &[u8], &[u8]) -> Vec<u8>
+{
+    let mut report = Report::default();
+    for section in sections {
+        let enc = section.has_param("ENCRYPT");
+        for record in &section.records {
+            let key = record.hex("KEY").unwrap_or(Vec::new());
+            let iv = record.hex("IV").unwrap_or(Vec::new());
+            let (pt, ct) = match (record.hex("PLAINTEXT"), record.hex("CIPHERTEXT")) {
+                (Some(pt), Some(ct)) => (pt, ct),
+                _ => continue,
+            };
+            let ok = if enc {
+                encrypt(&key, &iv, &pt) == ct
+            } else {
+                decrypt(&key, &iv, &ct) == pt
+            };
+            report.check_detail(ok, record, || format!("key={} iv={}", key.to_hex(), iv.to_hex()));
+        }
+    }
+    report
+}
+
+/// Entropy source handing out the `EntropyInputPR` values, one per reseed.
+struct Replay {
+    inputs: Vec<Vec<u8>>,
+}
+
+impl Rng for Replay {
+    fn next_u32(&mut self) -> u32 {
+        panic!("DRBG entropy is read with fill_bytes");
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        assert!(!self.inputs.is_empty(), "DRBG reseeded more often than the vector allows");
+        let input = self.inputs.remove(0);
+        assert_eq!(input.len(), dest.len(), "entropy length mismatch");
+        dest.copy_from_slice(&input);
+    }
+}
+
+/// SP 800-90A `drbgvectors` files. Only sections with the bracketed `name`
+/// parameter, like `[SHA-256]`, are run; `instantiate` builds the mechanism
+/// from `(entropy, nonce, personalization)`. Each record generates twice and
+/// `ReturnedBits` is the second output. With `[PredictionResistance = True]`
+/// every generate reseeds from the next `EntropyInputPR`; otherwise the
+/// record may reseed once up front with `EntropyInputReseed`.
+pub fn run_drbg<M, F>(sections: &[Section], name: &str, instantiate: F) -> Report
+    where M: Mechanism,
+          F: Fn(&[u8], &[u8], &[u8]) -> M
+{
+    let mut report = Report::default();
+    for section in sections.iter().filter(|s| s.has_param(name)) {
+        let pr = section.param("PredictionResistance") == Some("True");
+        for record in &section.records {
+            let (entropy, expected) = match (record.hex("EntropyInput"), record.hex("ReturnedBits")) {
+                (Some(e), Some(r)) => (e, r),
+                _ => continue,
+            };
+            let nonce = record.hex("Nonce").unwrap_or(Vec::new());
+            let pers = record.hex("PersonalizationString").unwrap_or(Vec::new());
+            let additional = record.get_all("AdditionalInput").iter()
+                .map(|a| a.from_hex().unwrap_or(Vec::new()))
+                .collect::<Vec<_>>();
+
+            let mut drbg = Drbg::new(instantiate(&entropy, &nonce, &pers));
+            if pr {
+                let inputs = record.get_all("EntropyInputPR").iter()
+                    .map(|e| e.from_hex().unwrap_or(Vec::new()))
+                    .collect();
+                drbg.set_entropy_source(Box::new(Replay { inputs: inputs }));
+                drbg.prediction_resistance = true;
+            } else if let Some(e) = record.hex("EntropyInputReseed") {
+                let a = record.hex("AdditionalInputReseed").unwrap_or(Vec::new());
+                drbg.reseed(&e, &a);
+            }
+
+            let mut out = vec![0_u8; expected.len()];
+            let ok = additional.len() == 2 &&
+                     drbg.generate(&mut out, &additional[0]).is_ok() &&
+                     drbg.generate(&mut out, &additional[1]).is_ok() &&
+                     out == expected;
+            report.check_detail(ok, record, || format!("got {}", out.to_hex()));
+        }
+    }
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use rust_crypto::sha2::Sha256;
+
+    use crypto::{aes, cbc, cfb, ofb};
+    use drbg::{CtrDrbg, HmacDrbg};
+    use sha1::Sha1;
+    use super::{load, parse, run_cipher, run_drbg, run_hash_monte, run_hash_msg, Report};
+
+    fn assert_passes(file: &str, report: Report) {
+        assert!(report.passed > 0, "{}: no vectors ran", file);
+        assert!(report.failed.is_empty(), "{}: failed {:?}", file, report.failed);
+    }
+
+    fn run_aes<E, D>(file: &str, encrypt: E, decrypt: D)
+        where E: Fn(&[u8], &[u8], &[u8]) -> Vec<u8>,
+              D: Fn(&[u8], &[u8], &[u8]) -> Vec<u8>
+    {
+        let sections = load(format!("./data/cavp/{}", file)).unwrap();
+        assert_passes(file, run_cipher(&sections, encrypt, decrypt));
+    }
+
+    #[test]
+    fn test_parse() {
+        let input = "# comment\n\
+                     \n\
+                     [L = 20]\n\
+                     [ENCRYPT]\n\
+                     \n\
+                     COUNT = 0\n\
+                     Msg = 00\n\
+                     AdditionalInput = \n\
+                     AdditionalInput = ab\n\
+                     \n\
+                     COUNT = 1\n\
+                     Msg = 0102\n";
+        let sections = parse(input).unwrap();
+        assert_eq!(1, sections.len());
+        assert_eq!(Some("20"), sections[0].param("L"));
+        assert!(sections[0].has_param("ENCRYPT"));
+        assert_eq!(2, sections[0].records.len());
+        assert_eq!(Some(vec![1, 2]), sections[0].records[1].hex("Msg"));
+        assert_eq!(vec!["", "ab"], sections[0].records[0].get_all("AdditionalInput"));
+
+        // failures carry what's needed to reproduce them
+        let cipher = parse("[ENCRYPT]\n\nCOUNT = 3\nKEY = 00ff\nIV = 01\nPLAINTEXT = 02\nCIPHERTEXT = 03\n")
+            .unwrap();
+        let report = run_cipher(&cipher, |_, _, m| m.to_vec(), |_, _, c| c.to_vec());
+        assert_eq!(vec!["record at line 3 (3): key=00ff iv=01".to_string()], report.failed);
+
+        let err = parse("[L = 20]\nnot an assignment\n").err().unwrap();
+        assert_eq!(2, err.line);
+        assert!(parse("[L = 20\n").is_err());
+    }
+
+    #[test]
+    fn test_sha1_short_msg() {
+        let sections = load("./data/cavp/SHA1ShortMsg.rsp").unwrap();
+        assert_passes("SHA1ShortMsg.rsp", run_hash_msg(&sections, &mut Sha1::new()));
+    }
+
+    #[test]
+    fn test_sha1_long_msg() {
+        let sections = load("./data/cavp/SHA1LongMsg.rsp").unwrap();
+        assert_passes("SHA1LongMsg.rsp", run_hash_msg(&sections, &mut Sha1::new()));
+    }
+
+    #[test]
+    fn test_sha1_monte() {
+        let sections = load("./data/cavp/SHA1Monte.rsp").unwrap();
+        assert_passes("SHA1Monte.rsp", run_hash_monte(&sections, &mut Sha1::new()));
+    }
+
+    #[test]
+    fn test_aes_ecb() {
+        for file in ["ECBGFSbox128.rsp", "ECBKeySbox128.rsp", "ECBVarTxt128.rsp",
+                     "ECBVarKey128.rsp", "ECBMMT128.rsp"].iter() {
+            run_aes(file,
+                    |k, _, m| aes::encrypt_blocks(k, m),
+                    |k, _, c| aes::decrypt_blocks(k, c));
+        }
+    }
+
+    #[test]
+    fn test_aes_cbc() {
+        for file in ["CBCGFSbox128.rsp", "CBCKeySbox128.rsp", "CBCVarTxt128.rsp",
+                     "CBCVarKey128.rsp", "CBCMMT128.rsp"].iter() {
+            run_aes(file, cbc::encrypt_blocks, cbc::decrypt_blocks);
+        }
+    }
+
+    #[test]
+    fn test_aes_cbc_padded() {
+        // the padded API must agree with the raw blocks, plus one pad block
+        run_aes("CBCMMT128.rsp",
+                |k, iv, m| {
+                    let mut c = cbc::encrypt(k, iv, m);
+                    c.truncate(m.len());
+                    c
+                },
+                |k, iv, c| {
+                    let mut padded = c.to_vec();
+                    padded.extend_from_slice(&cbc::encrypt_blocks(
+                        k, &c[c.len() - 16..], &[16_u8; 16]));
+                    cbc::decrypt(k, iv, &padded).unwrap()
+                });
+    }
+
+    #[test]
+    fn test_aes_ofb() {
+        for file in ["OFBMMT128.rsp", "SP800-38A_OFB.rsp"].iter() {
+            run_aes(file, ofb::crypt, ofb::crypt);
+        }
+    }
+
+    #[test]
+    fn test_aes_cfb() {
+        for file in ["CFB128MMT128.rsp", "SP800-38A_CFB128.rsp"].iter() {
+            run_aes(file, cfb::encrypt, cfb::decrypt);
+        }
+    }
+
+    #[test]
+    fn test_aes_ctr() {
+        run_aes("SP800-38A_CTR.rsp", aes::ctr_be, aes::ctr_be);
+    }
+
+    #[test]
+    fn test_hmac_drbg() {
+        let sha256 = |e: &[u8], n: &[u8], p: &[u8]| HmacDrbg::new(Sha256::new(), e, n, p);
+
+        let sections = load("./data/cavp/HMAC_DRBG.rsp").unwrap();
+        assert_passes("HMAC_DRBG.rsp", run_drbg(&sections, "SHA-256", sha256));
+
+        let sections = load("./data/drbg/pr_false/HMAC_DRBG.rsp").unwrap();
+        assert_passes("pr_false/HMAC_DRBG.rsp SHA-1",
+                      run_drbg(&sections, "SHA-1",
+                               |e, n, p| HmacDrbg::new(Sha1::new(), e, n, p)));
+        assert_passes("pr_false/HMAC_DRBG.rsp SHA-256",
+                      run_drbg(&sections, "SHA-256", sha256));
+    }
+
+    #[test]
+    fn test_ctr_drbg() {
+        let sections = load("./data/drbg/pr_false/CTR_DRBG.rsp").unwrap();
+        assert_passes("pr_false/CTR_DRBG.rsp use df",
+                      run_drbg(&sections, "AES-128 use df", CtrDrbg::new));
+        assert_passes("pr_false/CTR_DRBG.rsp no df",
+                      run_drbg(&sections, "AES-128 no df", |e, _, p| CtrDrbg::new_no_df(e, p)));
+    }
+}
@@ -0,0 +1,245 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, mpsc};
+use std::thread;
+
+use rand::{Rng, weak_rng};
+
+use util::write_u64_be;
+
+/// Two distinct inputs whose outputs agree on the first `bits` bits, and the
+/// number of function evaluations it took to find them.
+#[derive(Debug)]
+pub struct Collision {
+    pub a: Vec<u8>,
+    pub b: Vec<u8>,
+    pub digest: Vec<u8>,
+    pub evaluations: u64,
+}
+
+/// Keep the first `bits` bits of `digest`, zeroing the rest of the last byte.
+pub fn truncate(digest: &[u8], bits: usize) -> Vec<u8> {
+    let n = (bits + 7) / 8;
+    assert!(n <= digest.len(), "digest shorter than {} bits", bits);
+    let mut out = digest[..n].to_vec();
+    if bits % 8 != 0 {
+        out[n - 1] &= 0xff << (8 - bits % 8);
+    }
+    out
+}
+
+fn leading_zeros(x: &[u8]) -> usize {
+    let mut n = 0;
+    for &b in x {
+        if b == 0 {
+            n += 8;
+        } else {
+            return n + b.leading_zeros() as usize;
+        }
+    }
+    n
+}
+
+/// Classic birthday search: hash distinct inputs and remember every output
+/// until two agree. Needs about `2^(bits/2)` evaluations and as many table
+/// entries.
+pub fn find_table<F>(f: &F, bits: usize) -> Collision
+    where F: Fn(&[u8]) -> Vec<u8>
+{
+    let mut seen: HashMap<Vec<u8>, Vec<u8>> = HashMap::new();
+    let mut input = [0_u8; 8];
+    let mut evaluations = 0_u64;
+
+    for ctr in 0.. {
+        write_u64_be(&mut input, ctr);
+        let digest = truncate(&f(&input), bits);
+        evaluations += 1;
+        if let Some(other) = seen.get(&digest) {
+            return Collision {
+                a: other.clone(),
+                b: input.to_vec(),
+                digest: digest,
+                evaluations: evaluations,
+            };
+        }
+        seen.insert(digest, input.to_vec());
+    }
+    unreachable!()
+}
+
+/// Parallel Pollard rho with distinguished points (van Oorschot-Wiener).
+///
+/// Each thread walks `x -> trunc(f(x))` from random starting points until it
+/// reaches a distinguished point (one whose first `dp_bits` bits are zero)
+/// and records `(start, length)` under it in a shared table. Two walks that
+/// end in the same point have merged somewhere, and re-walking them in
+/// lockstep finds the colliding pair. Memory is about `2^(bits/2 - dp_bits)`
+/// table entries, and the work is about `2^(bits/2) + threads * 2^dp_bits`
+/// evaluations.
+pub fn find_rho<F>(f: F, bits: usize, dp_bits: usize, threads: usize) -> Collision
+    where F: Fn(&[u8]) -> Vec<u8> + Send + Sync + 'static
+{
+    assert!(dp_bits < bits);
+    assert!(threads > 0);
+
+    let f = Arc::new(f);
+    let done = Arc::new(AtomicBool::new(false));
+    let evaluations = Arc::new(AtomicUsize::new(0));
+    let table: Arc<Mutex<HashMap<Vec<u8>, (Vec<u8>, u64)>>> = Arc::new(Mutex::new(HashMap::new()));
+    let (tx, rx) = mpsc::channel();
+
+    // walks that run much longer than expected are probably stuck in a cycle
+    let max_len = 20_u64 << dp_bits;
+
+    let workers = (0..threads).map(|_| {
+        let f = f.clone();
+        let done = done.clone();
+        let evaluations = evaluations.clone();
+        let table = table.clone();
+        let tx = tx.clone();
+
+        thread::spawn(move || {
+            let mut rng = weak_rng();
+            let step = |x: &[u8]| truncate(&f(x), bits);
+
+            while !done.load(Ordering::Relaxed) {
+                let mut start = vec![0_u8; (bits + 7) / 8];
+                rng.fill_bytes(&mut start);
+                let start = truncate(&start, bits);
+
+                let mut x = start.clone();
+                let mut len = 0_u64;
+                while len < max_len && !done.load(Ordering::Relaxed) {
+                    x = step(&x);
+                    len += 1;
+                    if leading_zeros(&x) >= dp_bits {
+                        break;
+                    }
+                }
+                evaluations.fetch_add(len as usize, Ordering::Relaxed);
+                if leading_zeros(&x) < dp_bits {
+                    continue;
+                }
+
+                let other = {
+                    let mut t = table.lock().unwrap();
+                    match t.get(&x) {
+                        Some(other) => Some(other.clone()),
+                        None => {
+                            t.insert(x, (start.clone(), len));
+                            None
+                        }
+                    }
+                };
+
+                if let Some((other_start, other_len)) = other {
+                    if let Some((a, b, n)) = merge_point(&step, start, len, other_start, other_len) {
+                        evaluations.fetch_add(n as usize, Ordering::Relaxed);
+                        done.store(true, Ordering::Relaxed);
+                        let _ = tx.send((a, b));
+                        break;
+                    }
+                }
+            }
+        })
+    }).collect::<Vec<_>>();
+    drop(tx);
+
+    let (a, b) = rx.recv().unwrap();
+    for worker in workers {
+        worker.join().unwrap();
+    }
+
+    let digest = truncate(&f(&a), bits);
+    Collision {
+        a: a,
+        b: b,
+        digest: digest,
+        evaluations: evaluations.load(Ordering::SeqCst) as u64,
+    }
+}
+
+// Re-walk two chains that end in the same distinguished point and return the
+// two distinct inputs where they merge, along with the evaluations spent.
+// Returns `None` if one start lies on the other chain.
+fn merge_point<S>(step: &S, mut a: Vec<u8>, len_a: u64, mut b: Vec<u8>, len_b: u64)
+    -> Option<(Vec<u8>, Vec<u8>, u64)>
+    where S: Fn(&[u8]) -> Vec<u8>
+{
+    let mut evaluations = 0;
+    let (mut la, mut lb) = (len_a, len_b);
+    while la > lb {
+        a = step(&a);
+        la -= 1;
+        evaluations += 1;
+    }
+    while lb > la {
+        b = step(&b);
+        lb -= 1;
+        evaluations += 1;
+    }
+
+    while la > 0 {
+        if a == b {
+            return None;
+        }
+        let na = step(&a);
+        let nb = step(&b);
+        evaluations += 2;
+        if na == nb {
+            return Some((a, b, evaluations));
+        }
+        a = na;
+        b = nb;
+        la -= 1;
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use rust_crypto::digest::Digest;
+
+    use sha1::Sha1;
+    use super::{find_rho, find_table, truncate, Collision};
+
+    fn sha1(m: &[u8]) -> Vec<u8> {
+        let mut h = Sha1::new();
+        let mut out = vec![0_u8; 20];
+        h.input(m);
+        h.result(&mut out);
+        out
+    }
+
+    fn check(c: &Collision, bits: usize) {
+        assert!(c.a != c.b);
+        assert_eq!(truncate(&sha1(&c.a), bits), c.digest);
+        assert_eq!(truncate(&sha1(&c.b), bits), c.digest);
+        assert!(c.evaluations > 0);
+    }
+
+    #[test]
+    fn test_truncate() {
+        assert_eq!(vec![0x12, 0x30], truncate(&[0x12, 0x34, 0x56], 12));
+        assert_eq!(vec![0x12, 0x34], truncate(&[0x12, 0x34, 0x56], 16));
+        assert_eq!(vec![0x80], truncate(&[0xff], 1));
+    }
+
+    #[test]
+    fn test_find_table() {
+        let c = find_table(&sha1, 24);
+        check(&c, 24);
+    }
+
+    #[test]
+    fn test_find_rho() {
+        let c = find_rho(sha1, 32, 8, 4);
+        check(&c, 32);
+    }
+
+    #[test] #[ignore] // ~2^24 evaluations, run with --release
+    fn test_find_rho_48_bits() {
+        let c = find_rho(sha1, 48, 12, 8);
+        check(&c, 48);
+    }
+}
@@ -15,6 +15,9 @@ pub mod pkcs7 {
 
     pub fn unpad(mut data: Vec<u8>) -> Option<Vec<u8>> {
         let len = data.len();
+        if len == 0 {
+            return None;
+        }
         let pad = data[len - 1] as usize;
         if pad > len || pad == 0 {
             return None;
@@ -33,6 +36,48 @@ pub mod pkcs7 {
 pub mod aes {
     use ssl::symm::{self, Crypter};
 
+    fn ecb(key: &[u8], mode: symm::Mode, data: &[u8]) -> Vec<u8> {
+        assert!(data.len() % 16 == 0, "data must be a multiple of the AES block size");
+        let mut out = vec![0; data.len() + 16];
+        let mut cr = Crypter::new(symm::Cipher::aes_128_ecb(), mode, key, None).unwrap();
+        cr.pad(false);
+        let count = cr.update(data, &mut out).unwrap();
+        let rest = cr.finalize(&mut out[count..]).unwrap();
+        out.truncate(count + rest);
+        out
+    }
+
+    /// AES-128 over whole blocks with no padding.
+    pub fn encrypt_blocks(key: &[u8], data: &[u8]) -> Vec<u8> {
+        ecb(key, symm::Mode::Encrypt, data)
+    }
+
+    pub fn decrypt_blocks(key: &[u8], data: &[u8]) -> Vec<u8> {
+        ecb(key, symm::Mode::Decrypt, data)
+    }
+
+    /// Standard (SP 800-38A) CTR mode, where `iv` is the initial 16 byte
+    /// counter block and is incremented as a big-endian integer.
+    pub fn ctr_be(key: &[u8], iv: &[u8], data: &[u8]) -> Vec<u8> {
+        assert_eq!(iv.len(), 16);
+        let mut counter = iv.to_vec();
+        let mut blocks = Vec::with_capacity(data.len() + 16);
+        while blocks.len() < data.len() {
+            blocks.extend_from_slice(&counter);
+            for b in counter.iter_mut().rev() {
+                *b = b.wrapping_add(1);
+                if *b != 0 {
+                    break;
+                }
+            }
+        }
+        let keystream = encrypt_blocks(key, &blocks);
+        data.iter()
+            .zip(keystream)
+            .map(|(d, e)| d ^ e)
+            .collect::<Vec<_>>()
+    }
+
     pub fn ctr(key: &[u8], n: u64, data: &[u8]) -> Vec<u8> {
         let keystream = (0..)
             .flat_map(|c: u64| {
@@ -69,75 +114,114 @@ pub mod aes {
     }
 }
 
+pub mod ecb {
+    use super::{aes, pkcs7};
+
+    pub fn encrypt(key: &[u8], data: &[u8]) -> Vec<u8> {
+        aes::encrypt_blocks(key, &pkcs7::pad(data.to_vec(), 16))
+    }
+
+    pub fn decrypt(key: &[u8], data: &[u8]) -> Option<Vec<u8>> {
+        assert!(data.len() % 16 == 0, "data must be multiple length of blocksize");
+        pkcs7::unpad(aes::decrypt_blocks(key, data))
+    }
+}
+
 pub mod cbc {
-    use ssl::symm::{self, decrypt as ssl_decrypt, encrypt as ssl_encrypt};
-    use super::pkcs7;
+    use super::{aes, pkcs7};
 
-    pub fn encrypt(key: &[u8], iv: &[u8], data: &[u8]) -> Vec<u8> {
+    /// CBC over whole blocks, without padding.
+    pub fn encrypt_blocks(key: &[u8], iv: &[u8], data: &[u8]) -> Vec<u8> {
         let blocksize = key.len();
-        let mut data_ = data.to_vec();
-        data_ = pkcs7::pad(data_, blocksize);
+        assert!(data.len() % blocksize == 0, "data must be multiple length of blocksize");
 
-        let len = data_.len();
-        let blocks = len / blocksize;
-        let mut out: Vec<u8> = Vec::with_capacity(len);
+        let mut out: Vec<u8> = Vec::with_capacity(data.len());
+        let mut prev = iv.to_vec();
+
+        for block in data.chunks(blocksize) {
+            let m_i = block.iter()
+                .zip(&prev)
+                .map(|(a, b)| a ^ b)
+                .collect::<Vec<_>>();
+            prev = aes::encrypt_blocks(key, &m_i);
+            out.extend_from_slice(&prev);
+        }
+
+        out
+    }
 
-        for i in 0..blocks {
-            let mut m_i: Vec<u8> = Vec::with_capacity(blocksize);
+    pub fn decrypt_blocks(key: &[u8], iv: &[u8], data: &[u8]) -> Vec<u8> {
+        let blocksize = key.len();
+        assert!(data.len() % blocksize == 0, "data must be multiple length of blocksize");
 
-            let data_view = &data_[i*blocksize..(i+1)*blocksize];
-            if i == 0 {
-                for j in 0..blocksize {
-                    m_i.push(data_view[j] ^ iv[j]);
-                }
+        let mut out = aes::decrypt_blocks(key, data);
+        for i in (0..data.len() / blocksize).rev() {
+            let prev = if i == 0 {
+                iv
             } else {
-                let out_view = &out[(i-1)*blocksize..i*blocksize];
-                for j in 0..blocksize {
-                    m_i.push(data_view[j] ^ out_view[j]);
-                }
+                &data[(i-1)*blocksize..i*blocksize]
+            };
+            for j in 0..blocksize {
+                out[i*blocksize + j] ^= prev[j];
             }
-
-            m_i = ssl_encrypt(symm::Cipher::aes_128_ecb(), key, None, &m_i).unwrap();
-            m_i.truncate(blocksize);
-            out.extend_from_slice(&m_i);
         }
 
         out
     }
 
-    pub fn decrypt(key: &[u8], iv: &[u8], data: &[u8]) -> Option<Vec<u8>> {
+    pub fn encrypt(key: &[u8], iv: &[u8], data: &[u8]) -> Vec<u8> {
         let blocksize = key.len();
-        assert!(data.len() % blocksize == 0, "data must be multiple length of blocksize");
+        encrypt_blocks(key, iv, &pkcs7::pad(data.to_vec(), blocksize))
+    }
 
-        let blocks = data.len() / blocksize;
-        let zeros = vec![0_u8; key.len()];
-        let mut out: Vec<u8> = Vec::with_capacity(data.len());
+    pub fn decrypt(key: &[u8], iv: &[u8], data: &[u8]) -> Option<Vec<u8>> {
+        if data.len() % key.len() != 0 {
+            return None;
+        }
+        pkcs7::unpad(decrypt_blocks(key, iv, data))
+    }
+}
 
-        for i in 0..blocks {
-            let mut m_i =
-                if i == blocks - 1 {
-                    let mut block = data[i*blocksize..(i+1)*blocksize].to_vec();
-                    block.extend_from_slice(&zeros);
-                    ssl_decrypt(symm::Cipher::aes_128_ecb(), key, None, &block).unwrap()
-                } else {
-                    ssl_decrypt(symm::Cipher::aes_128_ecb(), key, None, &data[i*blocksize..(i+2)*blocksize]).unwrap()
-                };
-
-            if i == 0 {
-                for j in 0..blocksize {
-                    m_i[j] ^= iv[j];
-                }
-            } else {
-                let data_view = &data[(i-1)*blocksize..i*blocksize];
-                for j in 0..blocksize {
-                    m_i[j] ^= data_view[j];
-                }
-            }
+pub mod ofb {
+    use super::aes;
 
-            out.extend_from_slice(&m_i);
+    /// OFB is its own inverse.
+    pub fn crypt(key: &[u8], iv: &[u8], data: &[u8]) -> Vec<u8> {
+        let mut out = Vec::with_capacity(data.len());
+        let mut feedback = iv.to_vec();
+        for chunk in data.chunks(16) {
+            feedback = aes::encrypt_blocks(key, &feedback);
+            out.extend(chunk.iter().zip(&feedback).map(|(a, b)| a ^ b));
         }
+        out
+    }
+}
+
+pub mod cfb {
+    use super::aes;
+
+    /// Full-block (CFB128) cipher feedback.
+    pub fn encrypt(key: &[u8], iv: &[u8], data: &[u8]) -> Vec<u8> {
+        let mut out: Vec<u8> = Vec::with_capacity(data.len());
+        let mut feedback = iv.to_vec();
+        for chunk in data.chunks(16) {
+            let ks = aes::encrypt_blocks(key, &feedback);
+            let c = chunk.iter().zip(&ks).map(|(a, b)| a ^ b).collect::<Vec<_>>();
+            out.extend_from_slice(&c);
+            feedback = c;
+        }
+        out
+    }
 
-        pkcs7::unpad(out)
+    pub fn decrypt(key: &[u8], iv: &[u8], data: &[u8]) -> Vec<u8> {
+        let mut out = Vec::with_capacity(data.len());
+        let mut feedback = iv;
+        for chunk in data.chunks(16) {
+            let ks = aes::encrypt_blocks(key, feedback);
+            out.extend(chunk.iter().zip(&ks).map(|(a, b)| a ^ b));
+            feedback = chunk;
+        }
+        out
     }
 }
 
@@ -147,8 +231,7 @@ mod test {
     use std::fs::File;
 
     use ssl::symm::{self, decrypt, encrypt};
-    use serialize::base64::FromBase64;
-    use serialize::hex::ToHex;
+    use encoding::{FromBase64, ToHex};
 
     use super::pkcs7::{pad, unpad};
     use super::cbc;
@@ -175,6 +258,8 @@ mod test {
         a = "ABCD\x04\x04".as_bytes().to_vec();
         res = unpad(a);
         assert!(res.is_none());
+        res = unpad(Vec::new());
+        assert!(res.is_none());
     }
 
     #[test]
@@ -190,7 +275,7 @@ mod test {
         assert_eq!(&out1, &out2);
     }
 
-    #[test] #[ignore] // cbc::decrypt is broken i think
+    #[test]
     fn test_cbc_decrypt() {
         let mut f = File::open("./data/10.txt").unwrap();
         let mut s = String::new();
@@ -209,7 +294,7 @@ mod test {
         assert_eq!(&m1, &m2);
     }
 
-    #[test] #[ignore] // cbc::decrypt is broken i think
+    #[test]
     fn test_cbc_encrypt_decrypt() {
         let data = "DOS'T THOU JEER AND T-TAUNT ME IN THE TEETH?".as_bytes();
 
@@ -0,0 +1,466 @@
+use std::fmt;
+
+use rand::{Rng, SeedableRng};
+use rust_crypto::digest::Digest;
+use rust_crypto::hmac::Hmac;
+use rust_crypto::mac::Mac;
+use rust_crypto::sha2::Sha256;
+
+use crypto::aes;
+use util::xor_bytes;
+
+/// Largest request SP 800-90A allows either mechanism, 2^19 bits.
+pub const MAX_REQUEST: usize = 1 << 16;
+
+/// Requests allowed between reseeds, for both mechanisms.
+pub const RESEED_INTERVAL: u64 = 1 << 48;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DrbgError {
+    /// The reseed counter ran out, or prediction resistance was asked for,
+    /// and there's no entropy source to reseed from.
+    ReseedRequired,
+    RequestTooLarge,
+}
+
+impl fmt::Display for DrbgError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            DrbgError::ReseedRequired => write!(f, "reseed required but no entropy source"),
+            DrbgError::RequestTooLarge => write!(f, "more than {} bytes requested", MAX_REQUEST),
+        }
+    }
+}
+
+/// The algorithm specific half of a DRBG: its internal state and the
+/// update, reseed and generate steps. `Drbg` does the bookkeeping around it.
+pub trait Mechanism {
+    /// How much entropy to ask the entropy source for when reseeding.
+    fn entropy_len(&self) -> usize;
+
+    fn reseed(&mut self, entropy: &[u8], additional: &[u8]);
+
+    /// Fill `out`, which is at most `MAX_REQUEST` bytes.
+    fn generate(&mut self, out: &mut [u8], additional: &[u8]);
+}
+
+/// A deterministic random bit generator per NIST SP 800-90A, the properly
+/// seeded kind of CSPRNG to hold up against `MT19937Rng` and `weak_rng()`:
+/// without the state, its outputs say nothing about the ones before or
+/// after.
+///
+/// Keeps the reseed counter and handles prediction resistance: when either
+/// calls for fresh entropy it is drawn from the entropy source, if one was
+/// given, and generating fails with `ReseedRequired` otherwise.
+pub struct Drbg<M> {
+    mechanism: M,
+    reseed_counter: u64,
+    pub reseed_interval: u64,
+    pub prediction_resistance: bool,
+    entropy: Option<Box<Rng + Send>>,
+}
+
+impl<M: Mechanism> Drbg<M> {
+    /// Wrap an instantiated mechanism, e.g. `HmacDrbg::new(...)`.
+    pub fn new(mechanism: M) -> Drbg<M> {
+        Drbg {
+            mechanism: mechanism,
+            reseed_counter: 1,
+            reseed_interval: RESEED_INTERVAL,
+            prediction_resistance: false,
+            entropy: None,
+        }
+    }
+
+    /// Where entropy for automatic reseeds comes from, e.g. `OsRng`.
+    pub fn set_entropy_source(&mut self, source: Box<Rng + Send>) {
+        self.entropy = Some(source);
+    }
+
+    pub fn reseed_counter(&self) -> u64 {
+        self.reseed_counter
+    }
+
+    pub fn reseed(&mut self, entropy: &[u8], additional: &[u8]) {
+        self.mechanism.reseed(entropy, additional);
+        self.reseed_counter = 1;
+    }
+
+    /// Fill `out` with up to `MAX_REQUEST` bytes. With prediction
+    /// resistance, or when the reseed counter runs out, this reseeds first
+    /// with `additional`, which then isn't used again for the output.
+    pub fn generate(&mut self, out: &mut [u8], additional: &[u8]) -> Result<(), DrbgError> {
+        if out.len() > MAX_REQUEST {
+            return Err(DrbgError::RequestTooLarge);
+        }
+
+        let mut additional = additional;
+        if self.prediction_resistance || self.reseed_counter > self.reseed_interval {
+            let entropy = match self.entropy {
+                Some(ref mut source) => {
+                    let mut entropy = vec![0_u8; self.mechanism.entropy_len()];
+                    source.fill_bytes(&mut entropy);
+                    entropy
+                }
+                None => return Err(DrbgError::ReseedRequired),
+            };
+            self.reseed(&entropy, additional);
+            additional = &[];
+        }
+
+        self.mechanism.generate(out, additional);
+        self.reseed_counter += 1;
+        Ok(())
+    }
+}
+
+impl<M: Mechanism> Rng for Drbg<M> {
+    fn next_u32(&mut self) -> u32 {
+        let mut b = [0_u8; 4];
+        self.fill_bytes(&mut b);
+        b.iter().fold(0, |acc, &x| (acc << 8) | x as u32)
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        let mut b = [0_u8; 8];
+        self.fill_bytes(&mut b);
+        b.iter().fold(0, |acc, &x| (acc << 8) | x as u64)
+    }
+
+    /// Panics if a reseed is needed and there's no entropy source, since
+    /// `Rng` has no way to report it.
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        for chunk in dest.chunks_mut(MAX_REQUEST) {
+            if let Err(e) = self.generate(chunk, &[]) {
+                panic!("DRBG: {}", e);
+            }
+        }
+    }
+}
+
+/// HMAC_DRBG over any of the crate's digests.
+#[derive(Clone)]
+pub struct HmacDrbg<D> {
+    digest: D,
+    k: Vec<u8>,
+    v: Vec<u8>,
+}
+
+impl<D: Digest + Clone> HmacDrbg<D> {
+    /// Instantiate from `entropy` and a `nonce` (usually half as long), with
+    /// an optional personalization string.
+    pub fn new(digest: D, entropy: &[u8], nonce: &[u8], personalization: &[u8]) -> HmacDrbg<D> {
+        let n = digest.output_bytes();
+        let mut drbg = HmacDrbg {
+            digest: digest,
+            k: vec![0; n],
+            v: vec![1; n],
+        };
+        drbg.update(&[entropy, nonce, personalization]);
+        drbg
+    }
+
+    fn hmac(&self, key: &[u8], parts: &[&[u8]]) -> Vec<u8> {
+        let mut hmac = Hmac::new(self.digest.clone(), key);
+        for part in parts {
+            hmac.input(part);
+        }
+        hmac.result().code().to_vec()
+    }
+
+    /// The update function, with the provided data given in parts.
+    fn update(&mut self, provided: &[&[u8]]) {
+        let empty = provided.iter().all(|p| p.is_empty());
+        for &round in [0_u8, 1].iter() {
+            if round == 1 && empty {
+                break;
+            }
+            let round = [round];
+            let mut parts = vec![&self.v[..], &round[..]];
+            parts.extend_from_slice(provided);
+            self.k = self.hmac(&self.k, &parts);
+            self.v = self.hmac(&self.k, &[&self.v]);
+        }
+    }
+}
+
+impl<D: Digest + Clone> Mechanism for HmacDrbg<D> {
+    /// The digest's security strength from SP 800-57: 128 bits for SHA-1,
+    /// 192 for SHA-224 and 256 for anything bigger.
+    fn entropy_len(&self) -> usize {
+        let n = self.digest.output_bytes();
+        if n <= 20 {
+            16
+        } else if n <= 28 {
+            24
+        } else {
+            32
+        }
+    }
+
+    fn reseed(&mut self, entropy: &[u8], additional: &[u8]) {
+        self.update(&[entropy, additional]);
+    }
+
+    fn generate(&mut self, out: &mut [u8], additional: &[u8]) {
+        if !additional.is_empty() {
+            self.update(&[additional]);
+        }
+        for chunk in out.chunks_mut(self.v.len()) {
+            self.v = self.hmac(&self.k, &[&self.v]);
+            let n = chunk.len();
+            chunk.copy_from_slice(&self.v[..n]);
+        }
+        self.update(&[additional]);
+    }
+}
+
+const CTR_KEY_LEN: usize = 16;
+const CTR_BLOCK_LEN: usize = 16;
+const CTR_SEED_LEN: usize = CTR_KEY_LEN + CTR_BLOCK_LEN;
+
+/// CTR_DRBG over the crate's AES-128, with or without the derivation
+/// function. Without it, entropy must be full seed length (32 bytes) and
+/// personalization and additional input at most that.
+#[derive(Clone)]
+pub struct CtrDrbg {
+    key: Vec<u8>,
+    v: Vec<u8>,
+    use_df: bool,
+}
+
+impl CtrDrbg {
+    /// Instantiate with the derivation function from `entropy` (16 bytes
+    /// or more) and a `nonce`.
+    pub fn new(entropy: &[u8], nonce: &[u8], personalization: &[u8]) -> CtrDrbg {
+        let mut drbg = CtrDrbg::zero(true);
+        let seed = block_cipher_df(&[entropy, nonce, personalization]);
+        drbg.update(&seed);
+        drbg
+    }
+
+    /// Instantiate without the derivation function.
+    pub fn new_no_df(entropy: &[u8], personalization: &[u8]) -> CtrDrbg {
+        assert_eq!(CTR_SEED_LEN, entropy.len(), "entropy must be exactly the seed length");
+        let mut drbg = CtrDrbg::zero(false);
+        let seed = xor_bytes(entropy, &pad_seed(personalization));
+        drbg.update(&seed);
+        drbg
+    }
+
+    fn zero(use_df: bool) -> CtrDrbg {
+        CtrDrbg {
+            key: vec![0; CTR_KEY_LEN],
+            v: vec![0; CTR_BLOCK_LEN],
+            use_df: use_df,
+        }
+    }
+
+    fn next_block(&mut self) -> Vec<u8> {
+        for b in self.v.iter_mut().rev() {
+            *b = b.wrapping_add(1);
+            if *b != 0 {
+                break;
+            }
+        }
+        aes::encrypt_blocks(&self.key, &self.v)
+    }
+
+    fn update(&mut self, provided: &[u8]) {
+        let mut temp = Vec::with_capacity(CTR_SEED_LEN);
+        while temp.len() < CTR_SEED_LEN {
+            let block = self.next_block();
+            temp.extend_from_slice(&block);
+        }
+        let temp = xor_bytes(&temp, provided);
+        self.key = temp[..CTR_KEY_LEN].to_vec();
+        self.v = temp[CTR_KEY_LEN..].to_vec();
+    }
+
+    /// Additional input brought to seed length.
+    fn seed_input(&self, parts: &[&[u8]]) -> Vec<u8> {
+        if self.use_df {
+            block_cipher_df(parts)
+        } else {
+            let joined = parts.iter().flat_map(|p| p.iter().cloned()).collect::<Vec<_>>();
+            pad_seed(&joined)
+        }
+    }
+}
+
+impl Mechanism for CtrDrbg {
+    fn entropy_len(&self) -> usize {
+        if self.use_df { CTR_KEY_LEN } else { CTR_SEED_LEN }
+    }
+
+    fn reseed(&mut self, entropy: &[u8], additional: &[u8]) {
+        let seed = if self.use_df {
+            block_cipher_df(&[entropy, additional])
+        } else {
+            assert_eq!(CTR_SEED_LEN, entropy.len(), "entropy must be exactly the seed length");
+            xor_bytes(entropy, &pad_seed(additional))
+        };
+        self.update(&seed);
+    }
+
+    fn generate(&mut self, out: &mut [u8], additional: &[u8]) {
+        let additional = if additional.is_empty() {
+            vec![0; CTR_SEED_LEN]
+        } else {
+            let a = self.seed_input(&[additional]);
+            self.update(&a);
+            a
+        };
+        for chunk in out.chunks_mut(CTR_BLOCK_LEN) {
+            let block = self.next_block();
+            let n = chunk.len();
+            chunk.copy_from_slice(&block[..n]);
+        }
+        self.update(&additional);
+    }
+}
+
+fn pad_seed(data: &[u8]) -> Vec<u8> {
+    assert!(data.len() <= CTR_SEED_LEN, "input longer than the seed length");
+    let mut padded = data.to_vec();
+    padded.resize(CTR_SEED_LEN, 0);
+    padded
+}
+
+/// `BCC`: CBC-MAC under `key` with a zero IV.
+fn bcc(key: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut chain = vec![0_u8; CTR_BLOCK_LEN];
+    for block in data.chunks(CTR_BLOCK_LEN) {
+        chain = aes::encrypt_blocks(key, &xor_bytes(&chain, block));
+    }
+    chain
+}
+
+/// `Block_Cipher_df`, always returning seed length bytes, of the
+/// concatenation of `parts`.
+fn block_cipher_df(parts: &[&[u8]]) -> Vec<u8> {
+    let len = parts.iter().map(|p| p.len()).sum::<usize>() as u32;
+    let mut s = Vec::new();
+    s.extend_from_slice(&[(len >> 24) as u8, (len >> 16) as u8, (len >> 8) as u8, len as u8]);
+    s.extend_from_slice(&[0, 0, 0, CTR_SEED_LEN as u8]);
+    for part in parts {
+        s.extend_from_slice(part);
+    }
+    s.push(0x80);
+    while s.len() % CTR_BLOCK_LEN != 0 {
+        s.push(0);
+    }
+
+    let key = (0..CTR_KEY_LEN as u8).collect::<Vec<_>>();
+    let mut temp = Vec::with_capacity(CTR_SEED_LEN);
+    let mut i = 0_u8;
+    while temp.len() < CTR_SEED_LEN {
+        let mut block = vec![0, 0, 0, i];
+        block.resize(CTR_BLOCK_LEN, 0);
+        block.extend_from_slice(&s);
+        temp.extend_from_slice(&bcc(&key, &block));
+        i += 1;
+    }
+
+    let key = temp[..CTR_KEY_LEN].to_vec();
+    let mut x = temp[CTR_KEY_LEN..CTR_SEED_LEN].to_vec();
+    let mut out = Vec::with_capacity(CTR_SEED_LEN);
+    while out.len() < CTR_SEED_LEN {
+        x = aes::encrypt_blocks(&key, &x);
+        out.extend_from_slice(&x);
+    }
+    out
+}
+
+/// HMAC_DRBG with SHA-256, seeded with `seed` as its entropy input.
+impl<'a> SeedableRng<&'a [u8]> for Drbg<HmacDrbg<Sha256>> {
+    fn reseed(&mut self, seed: &'a [u8]) {
+        Drbg::reseed(self, seed, &[]);
+    }
+
+    fn from_seed(seed: &'a [u8]) -> Drbg<HmacDrbg<Sha256>> {
+        Drbg::new(HmacDrbg::new(Sha256::new(), seed, &[], &[]))
+    }
+}
+
+/// CTR_DRBG with the derivation function, so any seed length works.
+impl<'a> SeedableRng<&'a [u8]> for Drbg<CtrDrbg> {
+    fn reseed(&mut self, seed: &'a [u8]) {
+        Drbg::reseed(self, seed, &[]);
+    }
+
+    fn from_seed(seed: &'a [u8]) -> Drbg<CtrDrbg> {
+        Drbg::new(CtrDrbg::new(seed, &[], &[]))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{Rng, SeedableRng, StdRng};
+    use rust_crypto::sha2::Sha256;
+
+    use sha1::Sha1;
+    use super::{CtrDrbg, Drbg, DrbgError, HmacDrbg, MAX_REQUEST};
+
+    #[test]
+    fn test_reseed_counter() {
+        let mut drbg = Drbg::new(HmacDrbg::new(Sha1::new(), &[1; 16], &[2; 8], &[]));
+        drbg.reseed_interval = 2;
+        let mut out = [0_u8; 20];
+        assert_eq!(Ok(()), drbg.generate(&mut out, &[]));
+        assert_eq!(Ok(()), drbg.generate(&mut out, b"additional"));
+        assert_eq!(3, drbg.reseed_counter());
+        assert_eq!(Err(DrbgError::ReseedRequired), drbg.generate(&mut out, &[]));
+
+        drbg.reseed(&[3; 16], &[]);
+        assert_eq!(1, drbg.reseed_counter());
+        assert_eq!(Ok(()), drbg.generate(&mut out, &[]));
+
+        // with a source it reseeds itself
+        drbg.set_entropy_source(Box::new(StdRng::from_seed(&[4_usize][..])));
+        for _ in 0..5 {
+            assert_eq!(Ok(()), drbg.generate(&mut out, &[]));
+        }
+        assert!(drbg.reseed_counter() <= 3);
+
+        let mut big = vec![0_u8; MAX_REQUEST + 1];
+        assert_eq!(Err(DrbgError::RequestTooLarge), drbg.generate(&mut big, &[]));
+    }
+
+    #[test]
+    fn test_prediction_resistance() {
+        let mut drbg = Drbg::new(CtrDrbg::new(&[1; 16], &[2; 8], &[]));
+        drbg.prediction_resistance = true;
+        let mut out = [0_u8; 16];
+        assert_eq!(Err(DrbgError::ReseedRequired), drbg.generate(&mut out, &[]));
+
+        // fresh entropy on every request, so two copies part ways
+        drbg.set_entropy_source(Box::new(StdRng::from_seed(&[1_usize][..])));
+        let mut other = Drbg::new(CtrDrbg::new(&[1; 16], &[2; 8], &[]));
+        other.prediction_resistance = true;
+        other.set_entropy_source(Box::new(StdRng::from_seed(&[2_usize][..])));
+        let mut out2 = [0_u8; 16];
+        drbg.generate(&mut out, &[]).unwrap();
+        other.generate(&mut out2, &[]).unwrap();
+        assert!(out != out2);
+    }
+
+    #[test]
+    fn test_rng() {
+        let seed = b"a seed with plenty of entropy in it";
+        let mut a = Drbg::<HmacDrbg<Sha256>>::from_seed(&seed[..]);
+        let mut b = Drbg::<HmacDrbg<Sha256>>::from_seed(&seed[..]);
+        assert_eq!(a.gen::<u64>(), b.gen::<u64>());
+        assert_eq!(a.gen_iter::<u32>().take(100).collect::<Vec<_>>(),
+                   b.gen_iter::<u32>().take(100).collect::<Vec<_>>());
+        b.reseed(b"more entropy", &[]);
+        assert!(a.gen::<u64>() != b.gen::<u64>());
+
+        // longer than one request
+        let mut ctr = Drbg::<CtrDrbg>::from_seed(&seed[..]);
+        let mut buf = vec![0_u8; MAX_REQUEST + 100];
+        ctr.fill_bytes(&mut buf);
+        assert_eq!(3, ctr.reseed_counter());
+        assert!(buf[MAX_REQUEST..].iter().any(|&b| b != 0));
+    }
+}
@@ -0,0 +1,513 @@
+use std::cmp;
+use std::fmt;
+use std::io::{self, Read};
+
+const HEX_CHARS: &'static [u8] = b"0123456789abcdef";
+const BASE32_CHARS: &'static [u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ234567";
+const BASE64_STANDARD_CHARS: &'static [u8] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+const BASE64_URL_SAFE_CHARS: &'static [u8] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-_";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CharacterSet {
+    /// `+` and `/`
+    Standard,
+    /// `-` and `_`, safe in URLs and file names
+    UrlSafe,
+}
+
+/// Base64 flavour. With `pad`, encoded groups are filled out with `=` and
+/// decoding requires them; without, `=` is rejected.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Config {
+    pub char_set: CharacterSet,
+    pub pad: bool,
+}
+
+pub const STANDARD: Config = Config { char_set: CharacterSet::Standard, pad: true };
+pub const STANDARD_NO_PAD: Config = Config { char_set: CharacterSet::Standard, pad: false };
+pub const URL_SAFE: Config = Config { char_set: CharacterSet::UrlSafe, pad: true };
+pub const URL_SAFE_NO_PAD: Config = Config { char_set: CharacterSet::UrlSafe, pad: false };
+
+impl Config {
+    fn chars(&self) -> &'static [u8] {
+        match self.char_set {
+            CharacterSet::Standard => BASE64_STANDARD_CHARS,
+            CharacterSet::UrlSafe => BASE64_URL_SAFE_CHARS,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ErrorKind {
+    /// A byte outside the alphabet.
+    InvalidByte(u8),
+    /// Padding where there shouldn't be any, or input after it.
+    InvalidPadding,
+    /// The input ended partway through a group.
+    Truncated,
+}
+
+/// Why and where decoding failed. `offset` counts bytes of the input, line
+/// breaks included, so it points straight at the problem in a file.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DecodeError {
+    pub offset: usize,
+    pub kind: ErrorKind,
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.kind {
+            ErrorKind::InvalidByte(b) => {
+                write!(f, "invalid byte {:#04x} at offset {}", b, self.offset)
+            }
+            ErrorKind::InvalidPadding => write!(f, "invalid padding at offset {}", self.offset),
+            ErrorKind::Truncated => write!(f, "input truncated at offset {}", self.offset),
+        }
+    }
+}
+
+/// Encode in symbols of `bits` bits each, padding the last group of
+/// `group` symbols with `=` if asked to.
+fn encode(data: &[u8], chars: &[u8], bits: u32, group: usize, pad: bool) -> String {
+    let mut out = String::with_capacity((data.len() * 8 + bits as usize - 1) / bits as usize);
+    let mask = (1 << bits) - 1;
+    let (mut acc, mut nacc) = (0_u32, 0);
+    for &b in data {
+        acc = ((acc << 8) | b as u32) & 0xffff;
+        nacc += 8;
+        while nacc >= bits {
+            nacc -= bits;
+            out.push(chars[((acc >> nacc) & mask) as usize] as char);
+        }
+    }
+    if nacc > 0 {
+        out.push(chars[((acc << (bits - nacc)) & mask) as usize] as char);
+    }
+    if pad {
+        while out.len() % group != 0 {
+            out.push('=');
+        }
+    }
+    out
+}
+
+/// Incremental decoder for any of the encodings here. Line breaks are
+/// skipped anywhere, so wrapped files decode as they are.
+pub struct Decoder {
+    table: [u8; 256],
+    bits: u32,
+    group: usize,
+    pad: bool,
+    acc: u32,
+    nacc: u32,
+    offset: usize,
+    // symbols and `=` seen in the current group
+    symbols: usize,
+    padding: usize,
+    // a padded group ended the input
+    closed: bool,
+    // skip spaces and tabs too, not just line breaks
+    skip_spaces: bool,
+}
+
+const INVALID: u8 = 0xff;
+
+impl Decoder {
+    fn new(chars: &[u8], bits: u32, group: usize, pad: bool, any_case: bool) -> Decoder {
+        let mut table = [INVALID; 256];
+        for (i, &c) in chars.iter().enumerate() {
+            table[c as usize] = i as u8;
+            if any_case {
+                table[c.to_ascii_lowercase() as usize] = i as u8;
+                table[c.to_ascii_uppercase() as usize] = i as u8;
+            }
+        }
+        Decoder {
+            table: table,
+            bits: bits,
+            group: group,
+            pad: pad,
+            acc: 0,
+            nacc: 0,
+            offset: 0,
+            symbols: 0,
+            padding: 0,
+            closed: false,
+            skip_spaces: false,
+        }
+    }
+
+    /// Hex, in either case, ignoring any ASCII whitespace between digits.
+    pub fn hex() -> Decoder {
+        let mut decoder = Decoder::new(HEX_CHARS, 4, 2, false, true);
+        decoder.skip_spaces = true;
+        decoder
+    }
+
+    /// RFC 4648 base32 with padding, in either case.
+    pub fn base32() -> Decoder {
+        Decoder::new(BASE32_CHARS, 5, 8, true, true)
+    }
+
+    pub fn base64(config: Config) -> Decoder {
+        Decoder::new(config.chars(), 6, 4, config.pad, false)
+    }
+
+    fn error(&self, kind: ErrorKind) -> DecodeError {
+        DecodeError {
+            offset: self.offset,
+            kind: kind,
+        }
+    }
+
+    /// Whether a group may end after `symbols` symbols: only if the bits
+    /// left over are too few to have made another byte.
+    fn can_end_after(&self, symbols: usize) -> bool {
+        symbols == 0 || (symbols * self.bits as usize) % 8 < self.bits as usize
+    }
+
+    /// Decode the next chunk of input onto the end of `out`.
+    pub fn update(&mut self, input: &[u8], out: &mut Vec<u8>) -> Result<(), DecodeError> {
+        for &c in input {
+            let space = c == b' ' || c == b'\t' || c == 0x0c;
+            if c == b'\n' || c == b'\r' || (space && self.skip_spaces) {
+                self.offset += 1;
+                continue;
+            }
+
+            if self.closed {
+                return Err(self.error(ErrorKind::InvalidPadding));
+            }
+
+            if c == b'=' {
+                if !self.pad || self.symbols == 0 || !self.can_end_after(self.symbols) {
+                    return Err(self.error(ErrorKind::InvalidPadding));
+                }
+                self.padding += 1;
+                if self.symbols + self.padding == self.group {
+                    self.symbols = 0;
+                    self.padding = 0;
+                    self.closed = true;
+                }
+                self.offset += 1;
+                continue;
+            }
+
+            let v = self.table[c as usize];
+            if v == INVALID {
+                return Err(self.error(ErrorKind::InvalidByte(c)));
+            }
+            if self.padding > 0 {
+                return Err(self.error(ErrorKind::InvalidPadding));
+            }
+            self.acc = (self.acc << self.bits) | v as u32;
+            self.nacc += self.bits;
+            if self.nacc >= 8 {
+                self.nacc -= 8;
+                out.push((self.acc >> self.nacc) as u8);
+                self.acc &= (1 << self.nacc) - 1;
+            }
+            self.symbols = (self.symbols + 1) % self.group;
+            self.offset += 1;
+        }
+        Ok(())
+    }
+
+    /// Check the input ended on a group boundary, or on a partial group
+    /// where that's allowed.
+    pub fn finish(&self) -> Result<(), DecodeError> {
+        if self.padding > 0 {
+            Err(self.error(ErrorKind::Truncated))
+        } else if self.symbols == 0 || (!self.pad && self.can_end_after(self.symbols)) {
+            Ok(())
+        } else {
+            Err(self.error(ErrorKind::Truncated))
+        }
+    }
+
+    fn decode(mut self, input: &[u8]) -> Result<Vec<u8>, DecodeError> {
+        let mut out = Vec::with_capacity(input.len() * self.bits as usize / 8);
+        try!(self.update(input, &mut out));
+        try!(self.finish());
+        Ok(out)
+    }
+}
+
+/// Decodes a stream as it's read, like a line-wrapped base64 file wrapped
+/// with `Decoder::base64(STANDARD)`. Decoding errors come back as
+/// `InvalidData`, and keep coming back from every later read.
+pub struct DecodeReader<R> {
+    inner: R,
+    decoder: Decoder,
+    buf: Vec<u8>,
+    pos: usize,
+    done: bool,
+    error: Option<DecodeError>,
+}
+
+impl<R: Read> DecodeReader<R> {
+    pub fn new(inner: R, decoder: Decoder) -> DecodeReader<R> {
+        DecodeReader {
+            inner: inner,
+            decoder: decoder,
+            buf: Vec::new(),
+            pos: 0,
+            done: false,
+            error: None,
+        }
+    }
+}
+
+fn invalid_data(e: DecodeError) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, e.to_string())
+}
+
+impl<R: Read> Read for DecodeReader<R> {
+    fn read(&mut self, out: &mut [u8]) -> io::Result<usize> {
+        let mut chunk = [0_u8; 4096];
+        while self.pos == self.buf.len() && !self.done {
+            if let Some(e) = self.error {
+                return Err(invalid_data(e));
+            }
+            self.buf.clear();
+            self.pos = 0;
+            let n = try!(self.inner.read(&mut chunk));
+            let result = if n == 0 {
+                self.done = true;
+                self.decoder.finish()
+            } else {
+                self.decoder.update(&chunk[..n], &mut self.buf)
+            };
+            if let Err(e) = result {
+                // whatever the bad chunk decoded to before the error is
+                // dropped with it, so the output never skips input
+                self.buf.clear();
+                self.done = false;
+                self.error = Some(e);
+            }
+        }
+        let n = cmp::min(out.len(), self.buf.len() - self.pos);
+        out[..n].copy_from_slice(&self.buf[self.pos..self.pos + n]);
+        self.pos += n;
+        Ok(n)
+    }
+}
+
+pub trait ToHex {
+    /// Lowercase hex.
+    fn to_hex(&self) -> String;
+}
+
+impl ToHex for [u8] {
+    fn to_hex(&self) -> String {
+        encode(self, HEX_CHARS, 4, 2, false)
+    }
+}
+
+pub trait FromHex {
+    fn from_hex(&self) -> Result<Vec<u8>, DecodeError>;
+}
+
+impl FromHex for str {
+    fn from_hex(&self) -> Result<Vec<u8>, DecodeError> {
+        Decoder::hex().decode(self.as_bytes())
+    }
+}
+
+pub trait ToBase64 {
+    fn to_base64(&self, config: Config) -> String;
+}
+
+impl ToBase64 for [u8] {
+    fn to_base64(&self, config: Config) -> String {
+        encode(self, config.chars(), 6, 4, config.pad)
+    }
+}
+
+pub trait FromBase64 {
+    /// Decode padded standard base64.
+    fn from_base64(&self) -> Result<Vec<u8>, DecodeError> {
+        self.from_base64_config(STANDARD)
+    }
+
+    fn from_base64_config(&self, config: Config) -> Result<Vec<u8>, DecodeError>;
+}
+
+impl FromBase64 for str {
+    fn from_base64_config(&self, config: Config) -> Result<Vec<u8>, DecodeError> {
+        Decoder::base64(config).decode(self.as_bytes())
+    }
+}
+
+pub trait ToBase32 {
+    /// RFC 4648 base32, padded.
+    fn to_base32(&self) -> String;
+}
+
+impl ToBase32 for [u8] {
+    fn to_base32(&self) -> String {
+        encode(self, BASE32_CHARS, 5, 8, true)
+    }
+}
+
+pub trait FromBase32 {
+    fn from_base32(&self) -> Result<Vec<u8>, DecodeError>;
+}
+
+impl FromBase32 for str {
+    fn from_base32(&self) -> Result<Vec<u8>, DecodeError> {
+        Decoder::base32().decode(self.as_bytes())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::File;
+    use std::io::{self, Read};
+
+    use rand::{Rng, SeedableRng, XorShiftRng};
+
+    use super::{Config, DecodeError, DecodeReader, Decoder, ErrorKind, FromBase32, FromBase64,
+                FromHex, ToBase32, ToBase64, ToHex, STANDARD, STANDARD_NO_PAD, URL_SAFE,
+                URL_SAFE_NO_PAD};
+
+    fn err(offset: usize, kind: ErrorKind) -> Result<Vec<u8>, DecodeError> {
+        Err(DecodeError {
+            offset: offset,
+            kind: kind,
+        })
+    }
+
+    #[test]
+    fn test_rfc4648() {
+        let vectors = [("", "", ""),
+                       ("f", "Zg==", "MY======"),
+                       ("fo", "Zm8=", "MZXQ===="),
+                       ("foo", "Zm9v", "MZXW6==="),
+                       ("foob", "Zm9vYg==", "MZXW6YQ="),
+                       ("fooba", "Zm9vYmE=", "MZXW6YTB"),
+                       ("foobar", "Zm9vYmFy", "MZXW6YTBOI======")];
+        for &(raw, b64, b32) in vectors.iter() {
+            let raw = raw.as_bytes();
+            assert_eq!(b64, raw.to_base64(STANDARD));
+            assert_eq!(Ok(raw.to_vec()), b64.from_base64());
+            assert_eq!(b64.trim_right_matches('='), raw.to_base64(STANDARD_NO_PAD));
+            assert_eq!(b32, raw.to_base32());
+            assert_eq!(Ok(raw.to_vec()), b32.from_base32());
+            assert_eq!(Ok(raw.to_vec()), b32.to_lowercase().from_base32());
+        }
+        assert_eq!("666f6f626172", b"foobar".to_hex());
+        assert_eq!(Ok(b"foobar".to_vec()), "666F6f626172".from_hex());
+    }
+
+    #[test]
+    fn test_url_safe() {
+        let data = [0xfb_u8, 0xff, 0xbf, 0xfe];
+        assert_eq!("+/+//g==", data.to_base64(STANDARD));
+        assert_eq!("-_-__g==", data.to_base64(URL_SAFE));
+        assert_eq!("-_-__g", data.to_base64(URL_SAFE_NO_PAD));
+        assert_eq!(Ok(data.to_vec()), "-_-__g".from_base64_config(URL_SAFE_NO_PAD));
+        assert_eq!(err(0, ErrorKind::InvalidByte(b'-')), "-_-__g==".from_base64());
+    }
+
+    #[test]
+    fn test_errors() {
+        assert_eq!(err(3, ErrorKind::InvalidByte(b'g')), "abcg".from_hex());
+        assert_eq!(Ok(vec![0xab, 0xcd]), "a b\tc\r\nd ".from_hex());
+        assert_eq!(err(4, ErrorKind::InvalidByte(b'x')), "a b x".from_hex());
+        assert_eq!(err(3, ErrorKind::Truncated), "abc".from_hex());
+        assert_eq!(err(6, ErrorKind::InvalidByte(b' ')), "Zm9v\nY g==".from_base64());
+        assert_eq!(err(6, ErrorKind::Truncated), "Zm9vYg".from_base64());
+        assert_eq!(err(5, ErrorKind::Truncated), "Zm9vY".from_base64_config(STANDARD_NO_PAD));
+        assert_eq!(err(5, ErrorKind::InvalidPadding), "Zm9vY=".from_base64());
+        assert_eq!(err(6, ErrorKind::InvalidPadding), "Zm9vYg==".from_base64_config(URL_SAFE_NO_PAD));
+        assert_eq!(err(8, ErrorKind::InvalidPadding), "Zm9vYg==Zg==".from_base64());
+        assert_eq!(err(7, ErrorKind::Truncated), "Zm9vYg=".from_base64());
+        assert_eq!(err(3, ErrorKind::InvalidPadding), "MZX=====".from_base32());
+        assert_eq!("invalid byte 0x67 at offset 3",
+                   "abcg".from_hex().unwrap_err().to_string());
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let configs = [STANDARD, STANDARD_NO_PAD, URL_SAFE, URL_SAFE_NO_PAD];
+        let mut rng = XorShiftRng::from_seed([1, 2, 3, 4]);
+        for len in 0..200 {
+            let data = rng.gen_iter::<u8>().take(len).collect::<Vec<_>>();
+            assert_eq!(Ok(data.clone()), data.to_hex().from_hex());
+            assert_eq!(Ok(data.clone()), data.to_base32().from_base32());
+            for &config in configs.iter() {
+                let s = data.to_base64(config);
+                assert_eq!(Ok(data.clone()), s.from_base64_config(config));
+
+                // wrapped at an arbitrary width
+                let width = rng.gen_range(1, 80);
+                let wrapped = s.as_bytes()
+                    .chunks(width)
+                    .map(|l| String::from_utf8(l.to_vec()).unwrap())
+                    .collect::<Vec<_>>()
+                    .join("\r\n");
+                assert_eq!(Ok(data.clone()), wrapped.from_base64_config(config));
+            }
+        }
+    }
+
+    /// Hands out at most `n` bytes per read.
+    struct Trickle<R> {
+        inner: R,
+        n: usize,
+    }
+
+    impl<R: Read> Read for Trickle<R> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let n = if buf.len() < self.n { buf.len() } else { self.n };
+            self.inner.read(&mut buf[..n])
+        }
+    }
+
+    fn read_all<R: Read>(r: R, decoder: Decoder) -> io::Result<Vec<u8>> {
+        let mut out = Vec::new();
+        try!(DecodeReader::new(r, decoder).read_to_end(&mut out));
+        Ok(out)
+    }
+
+    #[test]
+    fn test_stream() {
+        let mut s = String::new();
+        File::open("./data/6.txt").unwrap().read_to_string(&mut s).unwrap();
+        let expected = s.from_base64().unwrap();
+        assert_eq!(2876, expected.len());
+
+        let f = File::open("./data/6.txt").unwrap();
+        assert_eq!(expected, read_all(f, Decoder::base64(STANDARD)).unwrap());
+        for &n in [1, 3, 61].iter() {
+            let r = Trickle { inner: s.as_bytes(), n: n };
+            assert_eq!(expected, read_all(r, Decoder::base64(STANDARD)).unwrap());
+        }
+
+        let e = read_all(&b"Zm9v\nYm*y\n"[..], Decoder::base64(STANDARD)).unwrap_err();
+        assert_eq!(io::ErrorKind::InvalidData, e.kind());
+        assert_eq!("invalid byte 0x2a at offset 7", e.to_string());
+        // the error sticks, rather than decoding carrying on past the bad chunk
+        let mut input = b"Zm9vYmFyZm9vYm*y".to_vec();
+        input.extend(b"Zm9vYmFy".iter().cycle().take(800));
+        let mut r = DecodeReader::new(Trickle { inner: &input[..], n: 12 },
+                                      Decoder::base64(STANDARD));
+        let mut buf = [0_u8; 100];
+        let n = r.read(&mut buf).unwrap();
+        assert_eq!(b"foobarfoo", &buf[..n]);
+        for _ in 0..3 {
+            let e = r.read(&mut buf).unwrap_err();
+            assert_eq!("invalid byte 0x2a at offset 14", e.to_string());
+        }
+
+        let e = read_all(&b"Zm9vYm\n"[..], Decoder::base64(STANDARD)).unwrap_err();
+        assert_eq!("input truncated at offset 7", e.to_string());
+
+        let config = Config { pad: false, ..URL_SAFE };
+        assert_eq!(b"foob".to_vec(), read_all(&b"Zm9vYg"[..], Decoder::base64(config)).unwrap());
+    }
+}
@@ -0,0 +1,149 @@
+use std::collections::HashMap;
+
+use rand::{Rng, weak_rng};
+
+use toyhash::{IteratedHash, find_block_collision};
+
+/// Kelsey-Schneier expandable message: `k` pieces, where piece `i` is either
+/// a single block or `2^i + 1` blocks, and both choices lead to the same
+/// chaining value. Any length between `k` and `k + 2^k - 1` blocks can be
+/// produced without changing the final state.
+pub struct ExpandableMessage {
+    pieces: Vec<(Vec<u8>, Vec<u8>)>,
+    block_size: usize,
+    state: u64,
+}
+
+impl ExpandableMessage {
+    /// Build an expandable message with `k` pieces starting from `state`.
+    /// Costs about `k * 2^(bits/2 + 1) + 2^k` compressions.
+    pub fn build<H, R>(h: &H, rng: &mut R, mut state: u64, k: usize) -> ExpandableMessage
+        where H: IteratedHash + ?Sized, R: Rng
+    {
+        let bs = h.block_size();
+        let mut pieces = Vec::with_capacity(k);
+
+        for i in 0..k {
+            // the long side is 2^i dummy blocks followed by one free block
+            let dummy = vec![0_u8; bs << i];
+            let dummy_state = h.chain(state, &dummy);
+
+            let c = find_block_collision(h, rng, state, dummy_state);
+
+            let mut long = dummy;
+            long.extend_from_slice(&c.block_b);
+            pieces.push((c.block_a, long));
+            state = c.state;
+        }
+
+        ExpandableMessage {
+            pieces: pieces,
+            block_size: bs,
+            state: state,
+        }
+    }
+
+    pub fn min_blocks(&self) -> usize {
+        self.pieces.len()
+    }
+
+    pub fn max_blocks(&self) -> usize {
+        self.pieces.len() + (1 << self.pieces.len()) - 1
+    }
+
+    /// The chaining value after any message produced by `produce`.
+    pub fn state(&self) -> u64 {
+        self.state
+    }
+
+    /// Produce a message of exactly `blocks` blocks.
+    pub fn produce(&self, blocks: usize) -> Vec<u8> {
+        assert!(blocks >= self.min_blocks() && blocks <= self.max_blocks(),
+                "expandable message can't produce {} blocks", blocks);
+
+        let extra = blocks - self.min_blocks();
+        let mut out = Vec::with_capacity(blocks * self.block_size);
+        for (i, &(ref short, ref long)) in self.pieces.iter().enumerate() {
+            if extra & (1 << i) != 0 {
+                out.extend_from_slice(long);
+            } else {
+                out.extend_from_slice(short);
+            }
+        }
+        out
+    }
+}
+
+/// Find a second preimage for a long `target` message: build an expandable
+/// message of `k` pieces, find a bridge block from its final state into one
+/// of the target's intermediate chaining values, then fill the prefix with
+/// the expandable message so the overall length (and thus the padding) is
+/// unchanged. Takes about `2^bits / 2^k` compressions to find the bridge, so
+/// the target should be at least `2^k + k + 1` blocks long.
+pub fn second_preimage<H: IteratedHash + ?Sized>(h: &H, target: &[u8], k: usize) -> Option<Vec<u8>> {
+    let bs = h.block_size();
+    let mut rng = weak_rng();
+    let exp = ExpandableMessage::build(h, &mut rng, h.initial_state(), k);
+
+    // intermediate states reachable with a prefix the expandable message can
+    // produce: bridging into the state after block j needs j - 1 prefix blocks
+    let mut targets: HashMap<u64, usize> = HashMap::new();
+    for (i, &s) in h.chaining_states(target).iter().enumerate() {
+        let j = i + 1;
+        if j > exp.min_blocks() && j - 1 <= exp.max_blocks() {
+            targets.entry(s).or_insert(j);
+        }
+    }
+    if targets.is_empty() {
+        return None;
+    }
+
+    let mut bridge = vec![0_u8; bs];
+    loop {
+        rng.fill_bytes(&mut bridge);
+        let s = h.compress(exp.state(), &bridge);
+        if let Some(&j) = targets.get(&s) {
+            let mut out = exp.produce(j - 1);
+            out.extend_from_slice(&bridge);
+            out.extend_from_slice(&target[j * bs..]);
+            return Some(out);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{Rng, weak_rng};
+
+    use toyhash::{IteratedHash, ToySha1, ToyMd4};
+    use super::{ExpandableMessage, second_preimage};
+
+    #[test]
+    fn test_expandable_message() {
+        let h = ToyMd4 { bits: 20 };
+        let mut rng = weak_rng();
+        let exp = ExpandableMessage::build(&h, &mut rng, 0xbeef, 6);
+        assert_eq!(6, exp.min_blocks());
+        assert_eq!(6 + 63, exp.max_blocks());
+
+        for blocks in exp.min_blocks()..exp.max_blocks() + 1 {
+            let msg = exp.produce(blocks);
+            assert_eq!(blocks * 64, msg.len());
+            assert_eq!(exp.state(), h.chain(0xbeef, &msg));
+        }
+    }
+
+    #[test]
+    fn test_second_preimage() {
+        let h = ToySha1 { bits: 24 };
+        let k = 10;
+        let mut rng = weak_rng();
+        let mut target = vec![0_u8; 64 * ((1 << k) + k + 8) + 17];
+        rng.fill_bytes(&mut target);
+
+        let forged = second_preimage(&h, &target, k).unwrap();
+        assert_eq!(target.len(), forged.len());
+        assert!(forged != target);
+        assert_eq!(h.hash(&target), h.hash(&forged));
+    }
+}
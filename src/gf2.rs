@@ -0,0 +1,588 @@
+use std::cmp;
+use std::ops::BitXorAssign;
+
+use rand::Rng;
+
+// pivots eliminated per pass in `BitMatrix::eliminate`, a multiple of 8
+const ELIM_BLOCK: usize = 32;
+
+fn words_for(bits: usize) -> usize {
+    (bits + 63) / 64
+}
+
+/// Linear algebra over GF(2) on dense, bit-packed vectors and matrices.
+///
+/// Bit `i` of a vector lives in word `i / 64` at bit `i % 64`. Matrices are
+/// stored row major with every row padded to a whole number of words, so a
+/// row is just a word slice and row operations are word-wise XORs.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct BitVec {
+    len: usize,
+    words: Vec<u64>,
+}
+
+impl BitVec {
+    pub fn zeros(len: usize) -> BitVec {
+        BitVec {
+            len: len,
+            words: vec![0; words_for(len)],
+        }
+    }
+
+    pub fn from_bools(bits: &[bool]) -> BitVec {
+        let mut v = BitVec::zeros(bits.len());
+        for (i, &b) in bits.iter().enumerate() {
+            v.set(i, b);
+        }
+        v
+    }
+
+    /// The low `len` bits of `x`, bit `i` of `x` becoming element `i`.
+    pub fn from_u64(x: u64, len: usize) -> BitVec {
+        assert!(len <= 64);
+        let mut v = BitVec::zeros(len);
+        if len > 0 {
+            v.words[0] = if len == 64 { x } else { x & ((1 << len) - 1) };
+        }
+        v
+    }
+
+    pub fn random<R: Rng>(rng: &mut R, len: usize) -> BitVec {
+        let mut v = BitVec::zeros(len);
+        for x in v.words.iter_mut() {
+            *x = rng.next_u64();
+        }
+        v.clear_padding();
+        v
+    }
+
+    fn clear_padding(&mut self) {
+        if self.len % 64 != 0 {
+            let last = self.words.len() - 1;
+            self.words[last] &= (1 << (self.len % 64)) - 1;
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn words(&self) -> &[u64] {
+        &self.words
+    }
+
+    pub fn get(&self, i: usize) -> bool {
+        assert!(i < self.len);
+        (self.words[i / 64] >> (i % 64)) & 1 == 1
+    }
+
+    pub fn set(&mut self, i: usize, b: bool) {
+        assert!(i < self.len);
+        if b {
+            self.words[i / 64] |= 1 << (i % 64);
+        } else {
+            self.words[i / 64] &= !(1 << (i % 64));
+        }
+    }
+
+    pub fn flip(&mut self, i: usize) {
+        assert!(i < self.len);
+        self.words[i / 64] ^= 1 << (i % 64);
+    }
+
+    pub fn dot(&self, other: &BitVec) -> bool {
+        assert_eq!(self.len, other.len);
+        let ones = self.words.iter()
+            .zip(other.words.iter())
+            .fold(0, |acc, (a, b)| acc ^ (a & b));
+        ones.count_ones() & 1 == 1
+    }
+
+    pub fn count_ones(&self) -> usize {
+        self.words.iter().map(|x| x.count_ones() as usize).sum()
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.words.iter().all(|&x| x == 0)
+    }
+
+    /// Indices of the set bits, in increasing order.
+    pub fn ones(&self) -> Vec<usize> {
+        let mut out = Vec::new();
+        for (i, &x) in self.words.iter().enumerate() {
+            let mut x = x;
+            while x != 0 {
+                out.push(64 * i + x.trailing_zeros() as usize);
+                x &= x - 1;
+            }
+        }
+        out
+    }
+}
+
+impl<'a> BitXorAssign<&'a BitVec> for BitVec {
+    fn bitxor_assign(&mut self, other: &'a BitVec) {
+        assert_eq!(self.len, other.len);
+        for (a, b) in self.words.iter_mut().zip(other.words.iter()) {
+            *a ^= *b;
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BitMatrix {
+    rows: usize,
+    cols: usize,
+    stride: usize,
+    data: Vec<u64>,
+}
+
+/// The result of Gauss-Jordan elimination: the pivot column of each of the
+/// first `rank` rows.
+pub struct Echelon {
+    pub pivots: Vec<usize>,
+}
+
+impl Echelon {
+    pub fn rank(&self) -> usize {
+        self.pivots.len()
+    }
+}
+
+impl BitMatrix {
+    pub fn zeros(rows: usize, cols: usize) -> BitMatrix {
+        let stride = words_for(cols);
+        BitMatrix {
+            rows: rows,
+            cols: cols,
+            stride: stride,
+            data: vec![0; rows * stride],
+        }
+    }
+
+    pub fn identity(n: usize) -> BitMatrix {
+        let mut m = BitMatrix::zeros(n, n);
+        for i in 0..n {
+            m.set(i, i, true);
+        }
+        m
+    }
+
+    pub fn from_rows(rows: &[BitVec]) -> BitMatrix {
+        assert!(!rows.is_empty());
+        let mut m = BitMatrix::zeros(rows.len(), rows[0].len());
+        for (i, row) in rows.iter().enumerate() {
+            m.set_row(i, row);
+        }
+        m
+    }
+
+    pub fn random<R: Rng>(rng: &mut R, rows: usize, cols: usize) -> BitMatrix {
+        let mut m = BitMatrix::zeros(rows, cols);
+        for i in 0..rows {
+            m.set_row(i, &BitVec::random(rng, cols));
+        }
+        m
+    }
+
+    pub fn rows(&self) -> usize {
+        self.rows
+    }
+
+    pub fn cols(&self) -> usize {
+        self.cols
+    }
+
+    pub fn get(&self, r: usize, c: usize) -> bool {
+        assert!(r < self.rows && c < self.cols);
+        (self.data[r * self.stride + c / 64] >> (c % 64)) & 1 == 1
+    }
+
+    pub fn set(&mut self, r: usize, c: usize, b: bool) {
+        assert!(r < self.rows && c < self.cols);
+        let x = &mut self.data[r * self.stride + c / 64];
+        if b {
+            *x |= 1 << (c % 64);
+        } else {
+            *x &= !(1 << (c % 64));
+        }
+    }
+
+    pub fn row_words(&self, r: usize) -> &[u64] {
+        &self.data[r * self.stride..(r + 1) * self.stride]
+    }
+
+    pub fn row(&self, r: usize) -> BitVec {
+        BitVec {
+            len: self.cols,
+            words: self.row_words(r).to_vec(),
+        }
+    }
+
+    pub fn set_row(&mut self, r: usize, row: &BitVec) {
+        assert_eq!(self.cols, row.len());
+        let stride = self.stride;
+        self.data[r * stride..(r + 1) * stride].copy_from_slice(row.words());
+    }
+
+    fn swap_rows(&mut self, a: usize, b: usize) {
+        if a == b {
+            return;
+        }
+        let (a, b) = (cmp::min(a, b), cmp::max(a, b));
+        let stride = self.stride;
+        let (lo, hi) = self.data.split_at_mut(b * stride);
+        lo[a * stride..(a + 1) * stride].swap_with_slice(&mut hi[..stride]);
+    }
+
+    // row[dst] ^= row[src], from word `from` on
+    fn xor_rows(&mut self, dst: usize, src: usize, from: usize) {
+        let stride = self.stride;
+        let (d, s) = if dst < src {
+            let (lo, hi) = self.data.split_at_mut(src * stride);
+            (&mut lo[dst * stride..(dst + 1) * stride], &hi[..stride])
+        } else {
+            let (lo, hi) = self.data.split_at_mut(dst * stride);
+            (&mut hi[..stride], &lo[src * stride..(src + 1) * stride])
+        };
+        for (a, b) in d[from..].iter_mut().zip(s[from..].iter()) {
+            *a ^= *b;
+        }
+    }
+
+    /// `self * v`.
+    pub fn mul_vec(&self, v: &BitVec) -> BitVec {
+        assert_eq!(self.cols, v.len());
+        let mut out = BitVec::zeros(self.rows);
+        for r in 0..self.rows {
+            let ones = self.row_words(r).iter()
+                .zip(v.words())
+                .fold(0, |acc, (a, b)| acc ^ (a & b));
+            if ones.count_ones() & 1 == 1 {
+                out.set(r, true);
+            }
+        }
+        out
+    }
+
+    /// `self * other`: each output row is the XOR of the rows of `other`
+    /// picked out by a row of `self`.
+    pub fn mul(&self, other: &BitMatrix) -> BitMatrix {
+        assert_eq!(self.cols, other.rows);
+        let mut out = BitMatrix::zeros(self.rows, other.cols);
+        let stride = out.stride;
+        for r in 0..self.rows {
+            let dst = &mut out.data[r * stride..(r + 1) * stride];
+            for k in self.row(r).ones() {
+                for (a, b) in dst.iter_mut().zip(other.row_words(k)) {
+                    *a ^= *b;
+                }
+            }
+        }
+        out
+    }
+
+    pub fn transpose(&self) -> BitMatrix {
+        let mut out = BitMatrix::zeros(self.cols, self.rows);
+        for r in 0..self.rows {
+            for c in self.row(r).ones() {
+                out.set(c, r, true);
+            }
+        }
+        out
+    }
+
+    // row[dst] ^= row[src] from word `from` on, mirrored in the right hand
+    // side
+    fn add_row(&mut self, rhs: &mut Option<&mut BitVec>, dst: usize, src: usize, from: usize) {
+        self.xor_rows(dst, src, from);
+        if let Some(ref mut b) = *rhs {
+            if b.get(src) {
+                b.flip(dst);
+            }
+        }
+    }
+
+    /// Reduce to reduced row echelon form in place, applying the same row
+    /// operations to the bits of `rhs` (one per row) when given.
+    ///
+    /// Pivots are found `ELIM_BLOCK` at a time and cleared from the rest of
+    /// the matrix in one pass, with tables of every XOR combination of each
+    /// 8 of the block's pivot rows ("method of four Russians"): a few table
+    /// lookups per row per block instead of one row update per pivot. Rows at or below the current rank are
+    /// zero left of the column being pivoted on, so every update can also
+    /// skip the words before the block.
+    pub fn eliminate(&mut self, rhs: Option<&mut BitVec>) -> Echelon {
+        let mut rhs = rhs;
+        if let Some(ref b) = rhs {
+            assert_eq!(self.rows, b.len());
+        }
+        let stride = self.stride;
+        let mut table = vec![0_u64; ELIM_BLOCK / 8 * 256 * stride];
+        let mut table_rhs = vec![false; ELIM_BLOCK / 8 * 256];
+        let mut pivots = Vec::new();
+        let mut rank = 0;
+        let mut c = 0;
+
+        while c < self.cols && rank < self.rows {
+            // find the block's pivots, reducing each candidate row by the
+            // pivots found so far before testing it
+            let from = c / 64;
+            let mut block = Vec::with_capacity(ELIM_BLOCK);
+            while block.len() < ELIM_BLOCK && c < self.cols && rank + block.len() < self.rows {
+                let top = rank + block.len();
+                let mut found = None;
+                for r in top..self.rows {
+                    for (i, &p) in block.iter().enumerate() {
+                        if self.get(r, p) {
+                            self.add_row(&mut rhs, r, rank + i, from);
+                        }
+                    }
+                    if self.get(r, c) {
+                        found = Some(r);
+                        break;
+                    }
+                }
+                if let Some(r) = found {
+                    self.swap_rows(top, r);
+                    if let Some(ref mut b) = rhs {
+                        let (x, y) = (b.get(top), b.get(r));
+                        b.set(top, y);
+                        b.set(r, x);
+                    }
+                    block.push(c);
+                }
+                c += 1;
+            }
+            if block.is_empty() {
+                break;
+            }
+
+            // clear each pivot column from the block's other pivot rows
+            let k = block.len();
+            for j in (1..k).rev() {
+                for i in 0..j {
+                    if self.get(rank + i, block[j]) {
+                        self.add_row(&mut rhs, rank + i, rank + j, from);
+                    }
+                }
+            }
+
+            // tables of every XOR combination of each group of 8 pivot rows
+            let groups = (k + 7) / 8;
+            for g in 0..groups {
+                let base = g * 256;
+                for idx in 1..1 << cmp::min(8, k - 8 * g) {
+                    let src = rank + 8 * g + (idx as u32).trailing_zeros() as usize;
+                    let prev = base + (idx & (idx - 1));
+                    let (done, rest) = table.split_at_mut((base + idx) * stride);
+                    let prev_row = &done[prev * stride..];
+                    let row = self.row_words(src);
+                    for w in from..stride {
+                        rest[w] = prev_row[w] ^ row[w];
+                    }
+                    table_rhs[base + idx] = table_rhs[prev] ^
+                        rhs.as_ref().map_or(false, |b| b.get(src));
+                }
+            }
+
+            // the pivot rows are clear in each other's columns, so the
+            // groups can be applied one after another
+            for r in 0..self.rows {
+                if r >= rank && r < rank + k {
+                    continue;
+                }
+                for g in 0..groups {
+                    let idx = block[8 * g..cmp::min(k, 8 * g + 8)]
+                        .iter()
+                        .enumerate()
+                        .fold(0, |acc, (i, &p)| acc | (self.get(r, p) as usize) << i);
+                    if idx == 0 {
+                        continue;
+                    }
+                    let entry = g * 256 + idx;
+                    let row = &mut self.data[r * stride..(r + 1) * stride];
+                    let combo = &table[entry * stride..(entry + 1) * stride];
+                    for w in from..stride {
+                        row[w] ^= combo[w];
+                    }
+                    if table_rhs[entry] {
+                        if let Some(ref mut b) = rhs {
+                            b.flip(r);
+                        }
+                    }
+                }
+            }
+
+            pivots.extend(block);
+            rank += k;
+        }
+
+        Echelon { pivots: pivots }
+    }
+
+    pub fn rank(&self) -> usize {
+        self.clone().eliminate(None).rank()
+    }
+
+    /// Some `x` with `self * x = b`, with every free variable set to zero,
+    /// or `None` if the system is inconsistent.
+    pub fn solve(&self, b: &BitVec) -> Option<BitVec> {
+        let mut m = self.clone();
+        let mut b = b.clone();
+        let echelon = m.eliminate(Some(&mut b));
+        if (echelon.rank()..self.rows).any(|r| b.get(r)) {
+            return None;
+        }
+
+        let mut x = BitVec::zeros(self.cols);
+        for (r, &c) in echelon.pivots.iter().enumerate() {
+            x.set(c, b.get(r));
+        }
+        Some(x)
+    }
+
+    /// A basis of the null space: one vector per free column.
+    pub fn kernel(&self) -> Vec<BitVec> {
+        let mut m = self.clone();
+        let echelon = m.eliminate(None);
+        let mut is_pivot = vec![false; self.cols];
+        for &c in &echelon.pivots {
+            is_pivot[c] = true;
+        }
+
+        (0..self.cols).filter(|&f| !is_pivot[f]).map(|f| {
+            let mut v = BitVec::zeros(self.cols);
+            v.set(f, true);
+            for (r, &c) in echelon.pivots.iter().enumerate() {
+                if m.get(r, f) {
+                    v.set(c, true);
+                }
+            }
+            v
+        }).collect()
+    }
+
+    pub fn inverse(&self) -> Option<BitMatrix> {
+        assert_eq!(self.rows, self.cols, "only square matrices have inverses");
+        let n = self.rows;
+        // eliminate [self | I] and read the inverse off the right half
+        let mut aug = BitMatrix::zeros(n, 2 * n);
+        for r in 0..n {
+            for c in self.row(r).ones() {
+                aug.set(r, c, true);
+            }
+            aug.set(r, n + r, true);
+        }
+        let echelon = aug.eliminate(None);
+        if echelon.rank() < n || echelon.pivots[n - 1] != n - 1 {
+            return None;
+        }
+
+        let mut inv = BitMatrix::zeros(n, n);
+        for r in 0..n {
+            for c in aug.row(r).ones() {
+                if c >= n {
+                    inv.set(r, c - n, true);
+                }
+            }
+        }
+        Some(inv)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Instant;
+
+    use rand::thread_rng;
+
+    use super::{BitMatrix, BitVec};
+
+    #[test]
+    fn test_bitvec() {
+        let mut v = BitVec::zeros(100);
+        v.set(3, true);
+        v.set(70, true);
+        v.flip(99);
+        assert_eq!(vec![3, 70, 99], v.ones());
+        assert_eq!(3, v.count_ones());
+
+        let w = BitVec::from_bools(&[false, false, false, true]);
+        assert_eq!(BitVec::from_u64(0b1000, 4), w);
+
+        let mut u = v.clone();
+        u ^= &v;
+        assert!(u.is_zero());
+        assert!(v.dot(&v));
+    }
+
+    #[test]
+    fn test_inverse() {
+        let mut rng = thread_rng();
+        for _ in 0..20 {
+            let a = BitMatrix::random(&mut rng, 100, 100);
+            match a.inverse() {
+                Some(inv) => {
+                    assert_eq!(BitMatrix::identity(100), a.mul(&inv));
+                    assert_eq!(BitMatrix::identity(100), inv.mul(&a));
+                },
+                None => assert!(a.rank() < 100),
+            }
+        }
+    }
+
+    #[test]
+    fn test_solve_and_kernel() {
+        // not weak_rng: XorShift is itself linear, so its "random" matrices
+        // never have rank above 128
+        let mut rng = thread_rng();
+        // more unknowns than equations: always solvable, big kernel
+        let a = BitMatrix::random(&mut rng, 150, 200);
+        let x = BitVec::random(&mut rng, 200);
+        let b = a.mul_vec(&x);
+        let y = a.solve(&b).unwrap();
+        assert_eq!(b, a.mul_vec(&y));
+
+        let rank = a.rank();
+        let kernel = a.kernel();
+        assert_eq!(200 - rank, kernel.len());
+        for v in &kernel {
+            assert!(a.mul_vec(v).is_zero());
+        }
+
+        // an inconsistent system
+        let mut a = BitMatrix::zeros(2, 2);
+        a.set(0, 0, true);
+        a.set(1, 0, true);
+        assert_eq!(None, a.solve(&BitVec::from_u64(0b01, 2)));
+        assert_eq!(None, a.inverse());
+    }
+
+    #[test]
+    fn test_mul_transpose() {
+        let mut rng = thread_rng();
+        let a = BitMatrix::random(&mut rng, 70, 130);
+        let b = BitMatrix::random(&mut rng, 130, 65);
+        let ab = a.mul(&b);
+        assert_eq!(ab.transpose(), b.transpose().mul(&a.transpose()));
+        assert_eq!(a, a.transpose().transpose());
+
+        let x = BitVec::random(&mut rng, 65);
+        assert_eq!(ab.mul_vec(&x), a.mul_vec(&b.mul_vec(&x)));
+    }
+
+    #[test] #[ignore] // run with --release
+    fn test_solve_20000() {
+        let mut rng = thread_rng();
+        let n = 20000;
+        let a = BitMatrix::random(&mut rng, n + 100, n);
+        let x = BitVec::random(&mut rng, n);
+        let b = a.mul_vec(&x);
+
+        let start = Instant::now();
+        let y = a.solve(&b).unwrap();
+        println!("solved {}x{} in {:?}", n + 100, n, start.elapsed());
+        assert_eq!(x, y);
+    }
+}
@@ -0,0 +1,347 @@
+use rand::Rng;
+
+use rust_crypto::digest::Digest;
+
+// Hash-based one-time signatures over any of the crate's digests.
+//
+// Both schemes are only secure if each key signs a single message: every
+// signature reveals secret hash preimages, and signatures on two different
+// messages reveal enough of them to sign messages nobody asked for. The
+// `forge` functions search for such messages.
+
+fn hash<D: Digest + Clone>(h: &D, data: &[u8]) -> Vec<u8> {
+    let mut h = h.clone();
+    let mut out = vec![0_u8; h.output_bytes()];
+    h.reset();
+    h.input(data);
+    h.result(&mut out);
+    out
+}
+
+fn bit(digest: &[u8], i: usize) -> usize {
+    ((digest[i / 8] >> (7 - i % 8)) & 1) as usize
+}
+
+/// Messages tried by the forgers: `prefix` followed by a counter.
+fn candidate(prefix: &[u8], ctr: u64) -> Vec<u8> {
+    let mut msg = prefix.to_vec();
+    msg.extend_from_slice(format!(" #{}", ctr).as_bytes());
+    msg
+}
+
+/// One pair of values per digest bit. In a secret key they are random, in
+/// the public key they are the hashes of the secret ones.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LamportKey {
+    pub pairs: Vec<[Vec<u8>; 2]>,
+}
+
+/// Lamport signatures: signing reveals, for each bit of `H(msg)`, the secret
+/// value for that bit.
+pub struct Lamport<D> {
+    h: D,
+}
+
+impl<D: Digest + Clone> Lamport<D> {
+    pub fn new(h: D) -> Lamport<D> {
+        Lamport { h: h }
+    }
+
+    /// Returns `(secret, public)`.
+    pub fn keygen<R: Rng>(&self, rng: &mut R) -> (LamportKey, LamportKey) {
+        let n = self.h.output_bits();
+        let size = self.h.output_bytes();
+        let mut secret = Vec::with_capacity(n);
+        let mut public = Vec::with_capacity(n);
+        for _ in 0..n {
+            let mut pair = [vec![0_u8; size], vec![0_u8; size]];
+            rng.fill_bytes(&mut pair[0]);
+            rng.fill_bytes(&mut pair[1]);
+            public.push([hash(&self.h, &pair[0]), hash(&self.h, &pair[1])]);
+            secret.push(pair);
+        }
+        (LamportKey { pairs: secret }, LamportKey { pairs: public })
+    }
+
+    pub fn sign(&self, secret: &LamportKey, msg: &[u8]) -> Vec<Vec<u8>> {
+        let digest = hash(&self.h, msg);
+        secret.pairs.iter()
+            .enumerate()
+            .map(|(i, pair)| pair[bit(&digest, i)].clone())
+            .collect()
+    }
+
+    pub fn verify(&self, public: &LamportKey, msg: &[u8], sig: &[Vec<u8>]) -> bool {
+        if sig.len() != public.pairs.len() {
+            return false;
+        }
+        let digest = hash(&self.h, msg);
+        public.pairs.iter()
+            .zip(sig.iter())
+            .enumerate()
+            .all(|(i, (pair, s))| hash(&self.h, s) == pair[bit(&digest, i)])
+    }
+
+    /// Collect the secret values revealed by `signed` and try up to `tries`
+    /// messages starting with `prefix` until one only needs revealed values.
+    /// With `k` signatures a random message works with probability about
+    /// `(1 - 2^-k)^n`, so it takes a handful of signatures before this is
+    /// practical against a full-size digest.
+    pub fn forge(&self, signed: &[(&[u8], &[Vec<u8>])], prefix: &[u8], tries: u64)
+        -> Option<(Vec<u8>, Vec<Vec<u8>>)>
+    {
+        let n = self.h.output_bits();
+        let mut known: Vec<[Option<Vec<u8>>; 2]> = (0..n).map(|_| [None, None]).collect();
+        for &(msg, sig) in signed {
+            let digest = hash(&self.h, msg);
+            for (i, s) in sig.iter().enumerate() {
+                known[i][bit(&digest, i)] = Some(s.clone());
+            }
+        }
+
+        for ctr in 0..tries {
+            let msg = candidate(prefix, ctr);
+            let digest = hash(&self.h, &msg);
+            let sig = known.iter()
+                .enumerate()
+                .map(|(i, k)| k[bit(&digest, i)].clone())
+                .collect::<Option<Vec<_>>>();
+            if let Some(sig) = sig {
+                return Some((msg, sig));
+            }
+        }
+        None
+    }
+}
+
+/// One hash chain per digit. Secret keys hold the chain starts, public keys
+/// the chain ends.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct WinternitzKey {
+    pub chains: Vec<Vec<u8>>,
+}
+
+/// Winternitz signatures with a checksum. The digest is split into `w`-bit
+/// digits `d`, followed by the digits of `sum(2^w - 1 - d)`, and each is
+/// signed by hashing its secret value `d` times. The public key is every
+/// chain hashed `2^w - 1` times. The checksum stops anyone from pushing a
+/// digit further along its chain, because some other digit would then have
+/// to move backwards.
+pub struct Winternitz<D> {
+    h: D,
+    w: usize,
+}
+
+impl<D: Digest + Clone> Winternitz<D> {
+    /// `w` is the number of bits signed per chain, between 1 and 16.
+    pub fn new(h: D, w: usize) -> Winternitz<D> {
+        assert!(w >= 1 && w <= 16, "w must be between 1 and 16");
+        Winternitz { h: h, w: w }
+    }
+
+    fn max_digit(&self) -> usize {
+        (1 << self.w) - 1
+    }
+
+    fn message_digits(&self) -> usize {
+        (self.h.output_bits() + self.w - 1) / self.w
+    }
+
+    fn checksum_digits(&self) -> usize {
+        let max_checksum = self.message_digits() * self.max_digit();
+        let mut n = 0;
+        while max_checksum >> (n * self.w) != 0 {
+            n += 1;
+        }
+        n
+    }
+
+    /// Number of hash chains in a key.
+    pub fn chains(&self) -> usize {
+        self.message_digits() + self.checksum_digits()
+    }
+
+    /// The digits of `H(msg)` followed by the checksum digits.
+    pub fn digits(&self, msg: &[u8]) -> Vec<usize> {
+        let digest = hash(&self.h, msg);
+        let mut digits = Vec::with_capacity(self.chains());
+        for i in 0..self.message_digits() {
+            let mut d = 0;
+            for j in 0..self.w {
+                let b = i * self.w + j;
+                d <<= 1;
+                if b < digest.len() * 8 {
+                    d |= bit(&digest, b);
+                }
+            }
+            digits.push(d);
+        }
+
+        let checksum: usize = digits.iter().map(|&d| self.max_digit() - d).sum();
+        for i in (0..self.checksum_digits()).rev() {
+            digits.push((checksum >> (i * self.w)) & self.max_digit());
+        }
+        digits
+    }
+
+    fn chain(&self, start: &[u8], steps: usize) -> Vec<u8> {
+        let mut x = start.to_vec();
+        for _ in 0..steps {
+            x = hash(&self.h, &x);
+        }
+        x
+    }
+
+    /// Returns `(secret, public)`.
+    pub fn keygen<R: Rng>(&self, rng: &mut R) -> (WinternitzKey, WinternitzKey) {
+        let size = self.h.output_bytes();
+        let secret = (0..self.chains()).map(|_| {
+            let mut x = vec![0_u8; size];
+            rng.fill_bytes(&mut x);
+            x
+        }).collect::<Vec<_>>();
+        let public = secret.iter()
+            .map(|x| self.chain(x, self.max_digit()))
+            .collect();
+        (WinternitzKey { chains: secret }, WinternitzKey { chains: public })
+    }
+
+    pub fn sign(&self, secret: &WinternitzKey, msg: &[u8]) -> Vec<Vec<u8>> {
+        self.digits(msg).iter()
+            .zip(secret.chains.iter())
+            .map(|(&d, x)| self.chain(x, d))
+            .collect()
+    }
+
+    pub fn verify(&self, public: &WinternitzKey, msg: &[u8], sig: &[Vec<u8>]) -> bool {
+        if sig.len() != public.chains.len() {
+            return false;
+        }
+        self.digits(msg).iter()
+            .zip(sig.iter())
+            .zip(public.chains.iter())
+            .all(|((&d, s), p)| self.chain(s, self.max_digit() - d) == *p)
+    }
+
+    /// Every signature reveals chain values at its digits, and anything
+    /// further along a chain can be computed. Given several signatures under
+    /// the same key, try up to `tries` messages starting with `prefix` until
+    /// every digit (checksum included) is at or past the earliest revealed
+    /// value of its chain. Two signatures with `w = 8` over SHA-1 are plenty.
+    pub fn forge(&self, signed: &[(&[u8], &[Vec<u8>])], prefix: &[u8], tries: u64)
+        -> Option<(Vec<u8>, Vec<Vec<u8>>)>
+    {
+        let mut earliest: Vec<Option<(usize, Vec<u8>)>> = vec![None; self.chains()];
+        for &(msg, sig) in signed {
+            for ((&d, s), e) in self.digits(msg).iter().zip(sig.iter()).zip(earliest.iter_mut()) {
+                let better = match *e {
+                    Some((known, _)) => d < known,
+                    None => true,
+                };
+                if better {
+                    *e = Some((d, s.clone()));
+                }
+            }
+        }
+
+        for ctr in 0..tries {
+            let msg = candidate(prefix, ctr);
+            let digits = self.digits(&msg);
+            let ok = digits.iter().zip(earliest.iter()).all(|(&d, e)| match *e {
+                Some((known, _)) => d >= known,
+                None => false,
+            });
+            if ok {
+                let sig = digits.iter()
+                    .zip(earliest.iter())
+                    .map(|(&d, e)| {
+                        let &(known, ref x) = e.as_ref().unwrap();
+                        self.chain(x, d - known)
+                    })
+                    .collect();
+                return Some((msg, sig));
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::weak_rng;
+
+    use md4::Md4;
+    use sha1::Sha1;
+    use super::{Lamport, Winternitz};
+
+    #[test]
+    fn test_lamport() {
+        let mut rng = weak_rng();
+        let l = Lamport::new(Sha1::new());
+        let (secret, public) = l.keygen(&mut rng);
+        assert_eq!(160, public.pairs.len());
+
+        let sig = l.sign(&secret, b"attack at dawn");
+        assert!(l.verify(&public, b"attack at dawn", &sig));
+        assert!(!l.verify(&public, b"attack at dusk", &sig));
+        assert!(!l.verify(&public, b"attack at dawn", &sig[1..]));
+    }
+
+    #[test]
+    fn test_lamport_key_reuse() {
+        let mut rng = weak_rng();
+        let l = Lamport::new(Md4::new());
+        let (secret, public) = l.keygen(&mut rng);
+
+        let msgs = (0..8).map(|i| format!("payment #{}", i).into_bytes()).collect::<Vec<_>>();
+        let sigs = msgs.iter().map(|m| l.sign(&secret, m)).collect::<Vec<_>>();
+        let signed = msgs.iter()
+            .zip(sigs.iter())
+            .map(|(m, s)| (&m[..], &s[..]))
+            .collect::<Vec<_>>();
+
+        // one signature reveals nothing usable, eight reveal both values for
+        // almost every bit
+        assert!(l.forge(&signed[..1], b"forged", 1000).is_none());
+        let (msg, sig) = l.forge(&signed, b"forged", 1000).unwrap();
+        assert!(msg.starts_with(b"forged"));
+        assert!(l.verify(&public, &msg, &sig));
+    }
+
+    #[test]
+    fn test_winternitz() {
+        let mut rng = weak_rng();
+        for &w in [1, 4, 8].iter() {
+            let wots = Winternitz::new(Sha1::new(), w);
+            let (secret, public) = wots.keygen(&mut rng);
+            assert_eq!(wots.chains(), public.chains.len());
+
+            let sig = wots.sign(&secret, b"attack at dawn");
+            assert!(wots.verify(&public, b"attack at dawn", &sig));
+            assert!(!wots.verify(&public, b"attack at dusk", &sig));
+        }
+
+        // 20 message digits, and a checksum of at most 20 * 255 needs two more
+        assert_eq!(22, Winternitz::new(Sha1::new(), 8).chains());
+    }
+
+    #[test]
+    fn test_winternitz_two_messages() {
+        let mut rng = weak_rng();
+        let wots = Winternitz::new(Sha1::new(), 8);
+        let (secret, public) = wots.keygen(&mut rng);
+
+        let m1 = b"transfer $10 to alice";
+        let m2 = b"transfer $20 to bob";
+        let s1 = wots.sign(&secret, m1);
+        let s2 = wots.sign(&secret, m2);
+
+        // a single signature can't be extended thanks to the checksum
+        assert!(wots.forge(&[(&m1[..], &s1[..])], b"transfer $1000000 to eve", 20000).is_none());
+
+        let signed = [(&m1[..], &s1[..]), (&m2[..], &s2[..])];
+        let (msg, sig) = wots.forge(&signed, b"transfer $1000000 to eve", 1 << 20).unwrap();
+        assert!(msg.starts_with(b"transfer $1000000 to eve"));
+        assert!(wots.verify(&public, &msg, &sig));
+    }
+}
@@ -0,0 +1,331 @@
+use std::collections::{HashMap, HashSet};
+use std::io::{self, Read, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, mpsc};
+use std::thread;
+
+use rand::{Rng, weak_rng};
+
+use toyhash::{IteratedHash, find_block_collision};
+use util::{read_u32_le, read_u64_le, write_u32_le, write_u64_le};
+
+const MAGIC: &'static [u8; 8] = b"DIAMOND1";
+
+/// Largest diamond and block `read_from` will accept, so a corrupt header
+/// can't ask for an absurd allocation.
+const MAX_K: usize = 24;
+const MAX_BLOCK_SIZE: usize = 64;
+
+/// Herding (Nostradamus) attack of Kelsey and Kohno.
+///
+/// A diamond structure is a binary tree of `2^k` leaf chaining values, where
+/// every pair of siblings is mapped to their parent by a single colliding
+/// block each. Once the root is known we can commit to the hash of a message
+/// we haven't written yet: any prefix is herded into the tree by searching
+/// for one linking block that lands on a leaf, followed by the `k` blocks on
+/// the path from that leaf to the root.
+pub struct Diamond {
+    bits: usize,
+    block_size: usize,
+    // levels[0] holds the leaves; each node stores its chaining value and the
+    // block that takes it to its parent.
+    levels: Vec<Vec<Node>>,
+    root: u64,
+}
+
+#[derive(Clone)]
+struct Node {
+    state: u64,
+    block: Vec<u8>,
+}
+
+impl Diamond {
+    /// Build a diamond of `2^k` leaves, spreading the `2^k - 1` collision
+    /// searches across `threads` worker threads. Each collision costs about
+    /// `2^(bits/2 + 1)` compressions.
+    pub fn build<H>(h: &H, k: usize, threads: usize) -> Diamond
+        where H: IteratedHash + Clone + Send + 'static
+    {
+        assert!(threads > 0);
+        let mut rng = weak_rng();
+        let mask = h.state_mask();
+
+        let mut seen = HashSet::new();
+        let mut leaves = Vec::with_capacity(1 << k);
+        while leaves.len() < (1 << k) {
+            let state = rng.next_u64() & mask;
+            if seen.insert(state) {
+                leaves.push(state);
+            }
+        }
+
+        let mut levels = Vec::with_capacity(k + 1);
+        let mut states = leaves;
+
+        for _ in 0..k {
+            let n_pairs = states.len() / 2;
+            let queue = Arc::new(Mutex::new((0..n_pairs).collect::<Vec<_>>()));
+            let states_arc = Arc::new(states.clone());
+            let (tx, rx) = mpsc::channel();
+
+            let workers = (0..threads).map(|_| {
+                let h = h.clone();
+                let queue = queue.clone();
+                let states = states_arc.clone();
+                let tx = tx.clone();
+
+                thread::spawn(move || {
+                    let mut rng = weak_rng();
+                    loop {
+                        let maybe_pair = {
+                            let mut q = queue.lock().unwrap();
+                            q.pop()
+                        };
+                        match maybe_pair {
+                            Some(i) => {
+                                let c = find_block_collision(
+                                    &h, &mut rng, states[2 * i], states[2 * i + 1]);
+                                tx.send((i, c)).unwrap();
+                            },
+                            None => break,
+                        }
+                    }
+                })
+            }).collect::<Vec<_>>();
+            drop(tx);
+
+            let mut level: Vec<Node> = states.iter()
+                .map(|&s| Node { state: s, block: Vec::new() })
+                .collect();
+            let mut next = vec![0_u64; n_pairs];
+
+            for (i, c) in rx.iter() {
+                level[2 * i].block = c.block_a;
+                level[2 * i + 1].block = c.block_b;
+                next[i] = c.state;
+            }
+            for worker in workers {
+                worker.join().unwrap();
+            }
+
+            levels.push(level);
+            states = next;
+        }
+
+        let root = states[0];
+        levels.push(vec![Node { state: root, block: Vec::new() }]);
+
+        Diamond {
+            bits: h.state_bits(),
+            block_size: h.block_size(),
+            levels: levels,
+            root: root,
+        }
+    }
+
+    pub fn k(&self) -> usize {
+        self.levels.len() - 1
+    }
+
+    pub fn root(&self) -> u64 {
+        self.root
+    }
+
+    /// The hash to publish ahead of time for a herded message whose prefix
+    /// takes `prefix_blocks` blocks.
+    pub fn prediction<H: IteratedHash + ?Sized>(&self, h: &H, prefix_blocks: usize) -> u64 {
+        let len = (prefix_blocks + 1 + self.k()) * self.block_size;
+        h.finalize(self.root, &[], len as u64)
+    }
+
+    /// Herd `prefix` into the diamond. The prefix is padded with spaces to
+    /// `prefix_blocks` blocks, so the result hashes to
+    /// `self.prediction(h, prefix_blocks)`. Finding the linking block takes
+    /// about `2^(bits - k)` compressions, spread across `threads` threads.
+    pub fn herd<H>(&self, h: &H, prefix: &[u8], prefix_blocks: usize, threads: usize) -> Vec<u8>
+        where H: IteratedHash + Clone + Send + 'static
+    {
+        assert_eq!(self.bits, h.state_bits(), "diamond was built for a different hash");
+        assert!(threads > 0);
+        let bs = self.block_size;
+        assert!(prefix.len() <= prefix_blocks * bs, "prefix doesn't fit in {} blocks", prefix_blocks);
+
+        let mut msg = prefix.to_vec();
+        msg.resize(prefix_blocks * bs, b' ');
+        let state = h.chain(h.initial_state(), &msg);
+
+        let leaves: HashMap<u64, usize> = self.levels[0].iter()
+            .enumerate()
+            .map(|(i, node)| (node.state, i))
+            .collect();
+        let leaves = Arc::new(leaves);
+
+        let done = Arc::new(AtomicBool::new(false));
+        let (tx, rx) = mpsc::channel();
+
+        let workers = (0..threads).map(|_| {
+            let h = h.clone();
+            let leaves = leaves.clone();
+            let done = done.clone();
+            let tx = tx.clone();
+
+            thread::spawn(move || {
+                let mut rng = weak_rng();
+                let mut block = vec![0_u8; bs];
+                while !done.load(Ordering::Relaxed) {
+                    rng.fill_bytes(&mut block);
+                    if let Some(&i) = leaves.get(&h.compress(state, &block)) {
+                        done.store(true, Ordering::Relaxed);
+                        let _ = tx.send((i, block));
+                        break;
+                    }
+                }
+            })
+        }).collect::<Vec<_>>();
+        drop(tx);
+
+        let (mut idx, link) = rx.recv().unwrap();
+        for worker in workers {
+            worker.join().unwrap();
+        }
+
+        msg.extend_from_slice(&link);
+        for level in &self.levels[..self.k()] {
+            msg.extend_from_slice(&level[idx].block);
+            idx /= 2;
+        }
+        msg
+    }
+
+    pub fn write_to<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        let mut buf4 = [0_u8; 4];
+        let mut buf8 = [0_u8; 8];
+
+        try!(w.write_all(MAGIC));
+        for &x in [self.bits, self.block_size, self.k()].iter() {
+            write_u32_le(&mut buf4, x as u32);
+            try!(w.write_all(&buf4));
+        }
+        for level in &self.levels[..self.k()] {
+            for node in level {
+                write_u64_le(&mut buf8, node.state);
+                try!(w.write_all(&buf8));
+                try!(w.write_all(&node.block));
+            }
+        }
+        write_u64_le(&mut buf8, self.root);
+        w.write_all(&buf8)
+    }
+
+    pub fn read_from<R: Read>(r: &mut R) -> io::Result<Diamond> {
+        let mut buf4 = [0_u8; 4];
+        let mut buf8 = [0_u8; 8];
+
+        try!(r.read_exact(&mut buf8));
+        if &buf8 != MAGIC {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "not a diamond structure"));
+        }
+        let mut header = [0_usize; 3];
+        for x in header.iter_mut() {
+            try!(r.read_exact(&mut buf4));
+            *x = read_u32_le(&buf4) as usize;
+        }
+        let (bits, block_size, k) = (header[0], header[1], header[2]);
+        // the header is untrusted, so bound everything it sizes and let the
+        // levels grow as nodes are actually read
+        if bits > 64 || block_size == 0 || block_size > MAX_BLOCK_SIZE || k > MAX_K {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "bad diamond header"));
+        }
+
+        let mut levels = Vec::new();
+        for l in 0..k {
+            let mut level = Vec::new();
+            for _ in 0..(1 << (k - l)) {
+                try!(r.read_exact(&mut buf8));
+                let mut block = vec![0_u8; block_size];
+                try!(r.read_exact(&mut block));
+                level.push(Node { state: read_u64_le(&buf8), block: block });
+            }
+            levels.push(level);
+        }
+        try!(r.read_exact(&mut buf8));
+        let root = read_u64_le(&buf8);
+        levels.push(vec![Node { state: root, block: Vec::new() }]);
+
+        Ok(Diamond {
+            bits: bits,
+            block_size: block_size,
+            levels: levels,
+            root: root,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{self, Cursor};
+
+    use toyhash::{IteratedHash, ToySha1};
+    use super::Diamond;
+
+    #[test]
+    fn test_diamond_structure() {
+        let h = ToySha1 { bits: 20 };
+        let d = Diamond::build(&h, 5, 4);
+        assert_eq!(5, d.k());
+
+        // every leaf reaches the root along its path
+        for (i, leaf) in d.levels[0].iter().enumerate() {
+            let mut state = leaf.state;
+            let mut idx = i;
+            for level in &d.levels[..d.k()] {
+                state = h.compress(state, &level[idx].block);
+                idx /= 2;
+            }
+            assert_eq!(d.root(), state);
+        }
+    }
+
+    #[test]
+    fn test_herding() {
+        let h = ToySha1 { bits: 20 };
+        let d = Diamond::build(&h, 6, 4);
+
+        let mut saved = Vec::new();
+        d.write_to(&mut saved).unwrap();
+        let d = Diamond::read_from(&mut Cursor::new(saved)).unwrap();
+
+        let prediction = d.prediction(&h, 2);
+
+        let results = b"2016 World Series: Cubs 8, Indians 7 (10 inn.)";
+        let msg = d.herd(&h, results, 2, 4);
+        assert!(msg.starts_with(results));
+        assert_eq!(prediction, h.hash(&msg));
+    }
+
+    #[test]
+    fn test_read_bad_magic() {
+        let bytes = b"NOTADIAMOND_____".to_vec();
+        assert!(Diamond::read_from(&mut Cursor::new(bytes)).is_err());
+    }
+
+    #[test]
+    fn test_read_bad_header() {
+        let header = |bits: u32, block_size: u32, k: u32| {
+            let mut bytes = b"DIAMOND1".to_vec();
+            for &x in [bits, block_size, k].iter() {
+                bytes.extend_from_slice(&[x as u8, (x >> 8) as u8, (x >> 16) as u8, (x >> 24) as u8]);
+            }
+            bytes
+        };
+        for &(bits, block_size, k) in [(20, 4, 31), (20, 1 << 31, 4), (65, 4, 4)].iter() {
+            let bytes = header(bits, block_size, k);
+            assert!(Diamond::read_from(&mut Cursor::new(bytes)).is_err());
+        }
+
+        // a plausible header over a truncated body fails without allocating it up front
+        let bytes = header(20, 64, 24);
+        assert_eq!(io::ErrorKind::UnexpectedEof,
+                   Diamond::read_from(&mut Cursor::new(bytes)).err().unwrap().kind());
+    }
+}
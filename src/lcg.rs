@@ -0,0 +1,732 @@
+use std::cmp;
+use std::num::Wrapping as w;
+
+use num::{BigInt, Integer, One, Zero, FromPrimitive, ToPrimitive};
+use rand::{Rng, SeedableRng};
+
+use gf2::{BitMatrix, BitVec};
+use math::{ModInv, closest_vector, lll_reduce};
+
+/// A linear congruential generator `x' = (a*x + c) mod m` that outputs its
+/// whole state, like glibc's `random()` with a `TYPE_0` state buffer or the
+/// `rand()` of many old C libraries. `m` is at most `2^32`, so everything
+/// fits in a `u64`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Lcg {
+    pub a: u64,
+    pub c: u64,
+    pub m: u64,
+    state: u64,
+}
+
+impl Lcg {
+    pub fn new(a: u64, c: u64, m: u64, seed: u64) -> Lcg {
+        assert!(m > 0 && m <= 1 << 32);
+        Lcg {
+            a: a % m,
+            c: c % m,
+            m: m,
+            state: seed % m,
+        }
+    }
+
+    pub fn state(&self) -> u64 {
+        self.state
+    }
+
+    pub fn next(&mut self) -> u64 {
+        self.state = (self.a * self.state + self.c) % self.m;
+        self.state
+    }
+
+    /// Recover the whole generator, modulus included, from consecutive
+    /// outputs. The differences `t_i = x_{i+1} - x_i` satisfy
+    /// `t_{i+1} = a*t_i mod m`, so every `t_{i+2}*t_i - t_{i+1}^2` is a
+    /// multiple of `m`, and a handful of them have a small multiple of `m`
+    /// as their gcd.
+    ///
+    /// Any divisor of the gcd above every output is a candidate modulus, and
+    /// a dozen outputs can fit `2m` or `m/2` as well as `m`. Every candidate
+    /// that fits is tried; if they all agree on what comes next the one with
+    /// the smallest modulus is returned.
+    ///
+    /// Returns the generator after the last output, or `None` if the outputs
+    /// were too few to pin the parameters down or fit generators that go on
+    /// to disagree.
+    pub fn crack(outputs: &[u64]) -> Option<Lcg> {
+        if outputs.len() < 4 {
+            return None;
+        }
+        let x = outputs.iter().map(|&v| BigInt::from_u64(v).unwrap()).collect::<Vec<_>>();
+        let t = x.windows(2).map(|p| &p[1] - &p[0]).collect::<Vec<_>>();
+        let g = t.windows(3)
+            .map(|t| &t[2] * &t[0] - &t[1] * &t[1])
+            .fold(BigInt::zero(), |g, u| g.gcd(&u));
+        if g.is_zero() {
+            return None;
+        }
+
+        // g = k*m for some k, and m is above every output and at most 2^32,
+        // so k lies in [g/2^32, g/(max+1)]. Walking k down walks m up.
+        let max = BigInt::from_u64(*outputs.iter().max().unwrap() + 1).unwrap();
+        let limit = BigInt::from_u64(1 << 32).unwrap();
+        let lowest = cmp::max(BigInt::one(), (&g + &limit - BigInt::one()) / &limit);
+        let mut k = &g / &max;
+        let mut found: Option<Lcg> = None;
+        while k >= lowest {
+            if (&g % &k).is_zero() {
+                if let Some(lcg) = Lcg::crack_with_modulus(outputs, (&g / &k).to_u64().unwrap()) {
+                    match found {
+                        None => found = Some(lcg),
+                        Some(ref first) if first.agrees_with(&lcg, 64) => (),
+                        Some(_) => return None,
+                    }
+                }
+            }
+            k = k - BigInt::one();
+        }
+        found
+    }
+
+    /// Whether two generators produce the same next `n` outputs.
+    fn agrees_with(&self, other: &Lcg, n: usize) -> bool {
+        let (mut x, mut y) = (self.clone(), other.clone());
+        (0..n).all(|_| x.next() == y.next())
+    }
+
+    /// Recover `a` and `c` from consecutive outputs when `m` is known.
+    pub fn crack_with_modulus(outputs: &[u64], m: u64) -> Option<Lcg> {
+        if outputs.len() < 3 || outputs.iter().any(|&v| v >= m) {
+            return None;
+        }
+        let x = outputs.iter().map(|&v| BigInt::from_u64(v).unwrap()).collect::<Vec<_>>();
+        let big_m = BigInt::from_u64(m).unwrap();
+
+        // a*t_i = t_{i+1} (mod m). t_i need not be invertible, in which case
+        // there are gcd(t_i, m) solutions; use the pair with the fewest.
+        let (t0, t1, g) = x.windows(3)
+            .map(|p| {
+                let t0 = (&p[1] - &p[0]).mod_floor(&big_m);
+                let g = t0.gcd(&big_m);
+                (t0, (&p[2] - &p[1]).mod_floor(&big_m), g)
+            })
+            .min_by_key(|&(_, _, ref g)| g.to_u64().unwrap())
+            .unwrap();
+        if t0.is_zero() || !(&t1 % &g).is_zero() {
+            return None;
+        }
+        let step = &big_m / &g;
+        let mut a = (&t1 / &g) * (&t0 / &g).modinv(&step) % &step;
+
+        while a < big_m {
+            let c = (&x[1] - &a * &x[0]).mod_floor(&big_m).to_u64().unwrap();
+            let mut lcg = Lcg::new(a.to_u64().unwrap(), c, m, outputs[0]);
+            if outputs[1..].iter().all(|&v| lcg.next() == v) {
+                return Some(lcg);
+            }
+            a = a + &step;
+        }
+        None
+    }
+}
+
+impl Rng for Lcg {
+    /// The raw state; only uniform over all 32 bits when `m = 2^32`.
+    fn next_u32(&mut self) -> u32 {
+        self.next() as u32
+    }
+}
+
+const GLIBC_DEG: usize = 31;
+const GLIBC_SEP: usize = 3;
+
+/// glibc's `rand()`/`random()` in the default `TYPE_3` configuration: not
+/// an LCG itself but an additive lagged Fibonacci generator
+/// `r[i] = r[i-31] + r[i-3] mod 2^32`, with its 31 words of state filled in
+/// by the Park-Miller LCG `16807*x mod (2^31-1)` and the first 310 outputs
+/// thrown away. Each output is `r[i] >> 1`.
+#[derive(Clone)]
+pub struct GlibcRandom {
+    r: [u32; GLIBC_DEG],
+    // r[i] holds the oldest word, r[i-31]
+    i: usize,
+}
+
+impl GlibcRandom {
+    /// `srand(seed)`.
+    pub fn new(seed: u32) -> GlibcRandom {
+        let seed = if seed == 0 { 1 } else { seed };
+        let mut words = vec![seed; GLIBC_DEG + GLIBC_SEP];
+        let mut word = seed as i32 as i64;
+        for i in 1..GLIBC_DEG {
+            // 16807*word mod 2^31-1 without overflowing 31 bits, exactly as
+            // glibc does it, seeds of 2^31 and up going negative
+            let hi = word / 127773;
+            let lo = word % 127773;
+            word = 16807 * lo - 2836 * hi;
+            if word < 0 {
+                word += 2147483647;
+            }
+            words[i] = word as u32;
+        }
+        for i in GLIBC_DEG..GLIBC_DEG + GLIBC_SEP {
+            words[i] = words[i - GLIBC_DEG];
+        }
+
+        let mut rng = GlibcRandom::from_words(&words[GLIBC_SEP..]);
+        for _ in 0..10 * GLIBC_DEG {
+            rng.rand();
+        }
+        rng
+    }
+
+    /// A generator whose next word is `words[0] + words[28]`, given the last
+    /// 31 words oldest first.
+    fn from_words(words: &[u32]) -> GlibcRandom {
+        let mut r = [0; GLIBC_DEG];
+        r.copy_from_slice(words);
+        GlibcRandom { r: r, i: 0 }
+    }
+
+    /// The next output, in `0..2^31`.
+    pub fn rand(&mut self) -> u32 {
+        let j = (self.i + GLIBC_DEG - GLIBC_SEP) % GLIBC_DEG;
+        let v = (w(self.r[self.i]) + w(self.r[j])).0;
+        self.r[self.i] = v;
+        self.i = (self.i + 1) % GLIBC_DEG;
+        v >> 1
+    }
+
+    /// Clone a generator from consecutive `rand()` outputs, returning it
+    /// positioned after the last one.
+    ///
+    /// Outputs drop the low bit `b` of each word, so
+    /// `o[i] = o[i-31] + o[i-3] + (b[i-31] & b[i-3])`: wherever that carry
+    /// shows up, both low bits are 1. The low bits obey the linear
+    /// recurrence `b[i] = b[i-31] ^ b[i-3]` over GF(2), so each carry gives
+    /// two equations in the first 31 low bits. A carry turns up a quarter of
+    /// the time; 150 outputs are nearly always enough.
+    pub fn from_outputs(outputs: &[u32]) -> Option<GlibcRandom> {
+        let n = outputs.len();
+        if n < GLIBC_DEG {
+            return None;
+        }
+
+        // each low bit as a combination of the first 31
+        let mut masks = (0..GLIBC_DEG).map(|j| 1_u64 << j).collect::<Vec<_>>();
+        let mut eqs = Vec::new();
+        for i in GLIBC_DEG..n {
+            let (a, b) = (masks[i - GLIBC_DEG], masks[i - GLIBC_SEP]);
+            masks.push(a ^ b);
+            let sum = outputs[i - GLIBC_DEG].wrapping_add(outputs[i - GLIBC_SEP]);
+            match outputs[i].wrapping_sub(sum) & 0x7fffffff {
+                0 => (),
+                1 => {
+                    eqs.push(a);
+                    eqs.push(b);
+                }
+                _ => return None,
+            }
+        }
+
+        let rows = eqs.iter().map(|&m| BitVec::from_u64(m, GLIBC_DEG)).collect::<Vec<_>>();
+        if rows.is_empty() {
+            return None;
+        }
+        let A = BitMatrix::from_rows(&rows);
+        let x = match A.solve(&BitVec::from_bools(&vec![true; rows.len()])) {
+            Some(x) => x.words()[0],
+            None => return None,
+        };
+
+        // missing carries rule out both low bits being set, which isn't
+        // linear, so any bits the carries leave free are settled by trying
+        // each choice against the whole sequence
+        let kernel = A.kernel().iter().map(|v| v.words()[0]).collect::<Vec<_>>();
+        if kernel.len() > 16 {
+            return None;
+        }
+        let mut found = (0..1_u32 << kernel.len()).filter_map(|choice| {
+            let low = kernel.iter().enumerate()
+                .filter(|&(j, _)| choice >> j & 1 == 1)
+                .fold(x, |acc, (_, &k)| acc ^ k);
+            let words = outputs.iter().zip(&masks)
+                .map(|(&o, &m)| (o << 1) | ((low & m).count_ones() & 1))
+                .collect::<Vec<_>>();
+            let consistent = (GLIBC_DEG..n).all(|i| {
+                words[i] == words[i - GLIBC_DEG].wrapping_add(words[i - GLIBC_SEP])
+            });
+            if consistent { Some(words) } else { None }
+        });
+
+        // more than one fit means too few outputs to tell them apart
+        match (found.next(), found.next()) {
+            (Some(words), None) => Some(GlibcRandom::from_words(&words[n - GLIBC_DEG..])),
+            _ => None,
+        }
+    }
+}
+
+impl SeedableRng<u32> for GlibcRandom {
+    fn reseed(&mut self, seed: u32) {
+        *self = GlibcRandom::new(seed);
+    }
+
+    fn from_seed(seed: u32) -> GlibcRandom {
+        GlibcRandom::new(seed)
+    }
+}
+
+impl Rng for GlibcRandom {
+    /// The top 16 bits of two outputs.
+    fn next_u32(&mut self) -> u32 {
+        let hi = self.rand() >> 15;
+        (hi << 16) | (self.rand() >> 15)
+    }
+}
+
+const JAVA_MULT: u64 = 0x5DEECE66D;
+const JAVA_MULT_INV: u64 = 0xDFE05BCB1365;
+const JAVA_ADD: u64 = 0xB;
+const JAVA_BITS: usize = 48;
+const JAVA_MASK: u64 = (1 << JAVA_BITS) - 1;
+
+/// `java.util.Random`: a 48-bit LCG whose methods only ever see the top
+/// `bits` of each new state, through `next(bits)`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct JavaRandom {
+    state: u64,
+}
+
+impl JavaRandom {
+    /// `new Random(seed)`, which scrambles the seed with the multiplier.
+    pub fn new(seed: i64) -> JavaRandom {
+        JavaRandom { state: (seed as u64 ^ JAVA_MULT) & JAVA_MASK }
+    }
+
+    /// A generator with the given (already scrambled) 48-bit state.
+    pub fn from_state(state: u64) -> JavaRandom {
+        JavaRandom { state: state & JAVA_MASK }
+    }
+
+    pub fn state(&self) -> u64 {
+        self.state
+    }
+
+    /// Step the LCG back `n` times, undoing `n` calls to `next`.
+    pub fn rewind(&mut self, n: usize) {
+        for _ in 0..n {
+            self.state = (w(self.state) - w(JAVA_ADD)).0.wrapping_mul(JAVA_MULT_INV) & JAVA_MASK;
+        }
+    }
+
+    pub fn next(&mut self, bits: usize) -> i32 {
+        assert!(bits > 0 && bits <= 32);
+        self.state = (w(self.state) * w(JAVA_MULT) + w(JAVA_ADD)).0 & JAVA_MASK;
+        (self.state >> (JAVA_BITS - bits)) as i32
+    }
+
+    pub fn next_int(&mut self) -> i32 {
+        self.next(32)
+    }
+
+    /// `nextInt(bound)`: the top bits for a power of two, otherwise
+    /// `next(31) % bound`, rejecting values from the last partial interval.
+    pub fn next_int_bound(&mut self, bound: i32) -> i32 {
+        assert!(bound > 0, "bound must be positive");
+        let mut r = self.next(31);
+        let m = bound - 1;
+        if bound & m == 0 {
+            return ((bound as i64 * r as i64) >> 31) as i32;
+        }
+        let mut u = r;
+        loop {
+            r = u % bound;
+            if u.wrapping_sub(r).wrapping_add(m) >= 0 {
+                return r;
+            }
+            u = self.next(31);
+        }
+    }
+
+    pub fn next_long(&mut self) -> i64 {
+        let hi = (self.next(32) as i64) << 32;
+        hi.wrapping_add(self.next(32) as i64)
+    }
+
+    pub fn next_double(&mut self) -> f64 {
+        let hi = (self.next(26) as i64) << 27;
+        (hi + self.next(27) as i64) as f64 * (1.0 / (1_u64 << 53) as f64)
+    }
+
+    /// Clone a generator from consecutive `nextInt()` outputs: the first
+    /// gives the top 32 bits of the state, leaving 2^16 candidates for the
+    /// second (and any later ones) to choose between.
+    pub fn crack_next_int(outputs: &[i32]) -> Option<JavaRandom> {
+        if outputs.len() < 2 {
+            return None;
+        }
+        let hi = (outputs[0] as u32 as u64) << 16;
+        (0..1 << 16).map(|lo| JavaRandom::from_state(hi | lo)).filter_map(|mut rng| {
+            if outputs[1..].iter().all(|&o| rng.next_int() == o) {
+                Some(rng)
+            } else {
+                None
+            }
+        }).next()
+    }
+
+    /// Clone a generator from one `nextDouble()`, which is made of the top
+    /// 26 bits of one state and the top 27 of the next: 2^22 candidates.
+    pub fn crack_next_double(x: f64) -> Option<JavaRandom> {
+        if !(x >= 0.0 && x < 1.0) {
+            return None;
+        }
+        let bits = (x * (1_u64 << 53) as f64) as u64;
+        let (hi, next) = ((bits >> 27) << 22, bits & ((1 << 27) - 1));
+        (0..1 << 22).map(|lo| JavaRandom::from_state(hi | lo)).filter_map(|mut rng| {
+            if rng.next(27) as u64 == next {
+                Some(rng)
+            } else {
+                None
+            }
+        }).next()
+    }
+
+    /// Clone a generator from the top `bits` bits of consecutive states,
+    /// e.g. the outputs of `nextInt(1 << bits)`, by solving a closest vector
+    /// problem.
+    ///
+    /// With `s_i = a^i s_0 + c_i mod 2^48`, the vectors `(s_i - c_i)` for
+    /// every possible `s_0` make up the lattice spanned by
+    /// `(1, a, a^2, ...) mod 2^48` and `2^48 e_i`. The outputs put each
+    /// `s_i` in a known interval, and the middle of those intervals is
+    /// close enough to the right lattice vector for Babai's algorithm on
+    /// an LLL-reduced basis to find it once there are a few more than
+    /// `48/bits` outputs.
+    pub fn crack_top_bits(outputs: &[u32], bits: usize) -> Option<JavaRandom> {
+        assert!(bits > 0 && bits <= 32);
+        let n = outputs.len();
+        if n < 2 {
+            return None;
+        }
+        let modulus = BigInt::one() << JAVA_BITS;
+        let unknown = JAVA_BITS - bits;
+
+        let mut powers = Vec::with_capacity(n);
+        let mut target = Vec::with_capacity(n);
+        let (mut a_i, mut c_i) = (1_u64, 0_u64);
+        for &v in outputs {
+            powers.push(BigInt::from_u64(a_i).unwrap());
+            let mid = ((v as u64) << unknown) + (1 << (unknown - 1));
+            target.push(BigInt::from_u64(mid).unwrap() - BigInt::from_u64(c_i).unwrap());
+            a_i = a_i.wrapping_mul(JAVA_MULT) & JAVA_MASK;
+            c_i = (w(c_i) * w(JAVA_MULT) + w(JAVA_ADD)).0 & JAVA_MASK;
+        }
+
+        let mut basis = vec![powers];
+        for i in 1..n {
+            let mut row = vec![BigInt::zero(); n];
+            row[i] = modulus.clone();
+            basis.push(row);
+        }
+        let basis = lll_reduce(&basis);
+        let closest = closest_vector(&basis, &target);
+        let s0 = match closest[0].mod_floor(&modulus).to_u64() {
+            Some(s) => s,
+            None => return None,
+        };
+
+        let mut rng = JavaRandom::from_state(s0);
+        if s0 >> unknown != outputs[0] as u64 {
+            return None;
+        }
+        if outputs[1..].iter().all(|&v| rng.next(bits) as u32 == v) {
+            Some(rng)
+        } else {
+            None
+        }
+    }
+}
+
+impl SeedableRng<u64> for JavaRandom {
+    fn reseed(&mut self, seed: u64) {
+        *self = JavaRandom::new(seed as i64);
+    }
+
+    fn from_seed(seed: u64) -> JavaRandom {
+        JavaRandom::new(seed as i64)
+    }
+}
+
+impl Rng for JavaRandom {
+    fn next_u32(&mut self) -> u32 {
+        self.next_int() as u32
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.next_long() as u64
+    }
+
+    fn next_f64(&mut self) -> f64 {
+        self.next_double()
+    }
+}
+
+/// The Visual C++ runtime's `rand()`: `x' = 214013*x + 2531011 mod 2^32`,
+/// returning bits 16 to 30.
+#[derive(Clone, Debug, PartialEq)]
+pub struct MsvcRand {
+    state: u32,
+}
+
+impl MsvcRand {
+    /// `srand(seed)`.
+    pub fn new(seed: u32) -> MsvcRand {
+        MsvcRand { state: seed }
+    }
+
+    /// The next output, in `0..2^15`.
+    pub fn rand(&mut self) -> u32 {
+        self.state = (w(self.state) * w(214013) + w(2531011)).0;
+        (self.state >> 16) & 0x7fff
+    }
+
+    /// Clone a generator from consecutive outputs by trying all 2^16 low
+    /// halves of the state behind the first. Bit 31 never reaches an
+    /// output, so it's left clear. Three outputs are usually enough to
+    /// leave one candidate.
+    pub fn crack(outputs: &[u32]) -> Option<MsvcRand> {
+        if outputs.len() < 2 {
+            return None;
+        }
+        let hi = outputs[0] << 16;
+        (0..1 << 16).map(|lo| MsvcRand::new(hi | lo)).filter_map(|mut rng| {
+            if outputs[1..].iter().all(|&o| rng.rand() == o) {
+                Some(rng)
+            } else {
+                None
+            }
+        }).next()
+    }
+}
+
+impl SeedableRng<u32> for MsvcRand {
+    fn reseed(&mut self, seed: u32) {
+        self.state = seed;
+    }
+
+    fn from_seed(seed: u32) -> MsvcRand {
+        MsvcRand::new(seed)
+    }
+}
+
+impl Rng for MsvcRand {
+    /// Three outputs packed together, high bits first.
+    fn next_u32(&mut self) -> u32 {
+        let a = self.rand() << 17;
+        let b = self.rand() << 2;
+        a | b | (self.rand() >> 13)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::File;
+    use std::io::{BufRead, BufReader};
+
+    use rand::{Rng, SeedableRng, thread_rng};
+
+    use util::MT19937Rng;
+    use super::{GlibcRandom, JavaRandom, Lcg, MsvcRand};
+
+    /// Sections of a file under data/lcg: a header line naming how the
+    /// generator was seeded and called, then one output per line.
+    fn read_data(name: &str) -> Vec<(String, Vec<String>)> {
+        let f = File::open(format!("./data/lcg/{}", name)).unwrap();
+        let mut sections: Vec<(String, Vec<String>)> = Vec::new();
+        for line in BufReader::new(f).lines() {
+            let line = line.unwrap();
+            if line.starts_with('#') || line.is_empty() {
+                continue;
+            }
+            if line.chars().next().unwrap().is_alphabetic() {
+                sections.push((line, Vec::new()));
+            } else {
+                sections.last_mut().unwrap().1.push(line);
+            }
+        }
+        sections
+    }
+
+    fn numbers<T: ::std::str::FromStr>(values: &[String]) -> Vec<T> {
+        values.iter().map(|v| v.parse().ok().unwrap()).collect()
+    }
+
+    #[test]
+    fn test_glibc() {
+        for (header, values) in read_data("glibc.txt") {
+            let args = header.split(' ').collect::<Vec<_>>();
+            let seed = args[1].parse().unwrap();
+            let expected = numbers::<u64>(&values);
+            match args[0] {
+                "srand" => {
+                    let mut rng = GlibcRandom::new(seed as u32);
+                    let got = expected.iter().map(|_| rng.rand() as u64).collect::<Vec<_>>();
+                    assert_eq!(expected, got, "{}", header);
+                }
+                "initstate" => {
+                    let mut rng = Lcg::new(1103515245, 12345, 1 << 31, seed);
+                    let got = expected.iter().map(|_| rng.next()).collect::<Vec<_>>();
+                    assert_eq!(expected, got, "{}", header);
+                }
+                _ => panic!("unknown section {}", header),
+            }
+        }
+    }
+
+    #[test]
+    fn test_glibc_clone() {
+        for (header, values) in read_data("glibc.txt") {
+            let outputs = numbers::<u32>(&values);
+            if header.starts_with("initstate") {
+                let mut lcg = Lcg::crack(&outputs[..10].iter().map(|&o| o as u64).collect::<Vec<_>>())
+                    .unwrap();
+                assert_eq!((1103515245, 12345, 1 << 31), (lcg.a, lcg.c, lcg.m));
+                assert!(outputs[10..].iter().all(|&o| lcg.next() == o as u64));
+                continue;
+            }
+
+            let mut rng = GlibcRandom::from_outputs(&outputs[..150]).unwrap();
+            assert!(outputs[150..].iter().all(|&o| rng.rand() == o), "{}", header);
+        }
+
+        // too few outputs, or not from this generator
+        let mut rng = GlibcRandom::new(1);
+        let outputs = (0..40).map(|_| rng.rand()).collect::<Vec<_>>();
+        assert!(GlibcRandom::from_outputs(&outputs).is_none());
+        let outputs = thread_rng().gen_iter::<u32>().map(|o| o >> 1).take(200).collect::<Vec<_>>();
+        assert!(GlibcRandom::from_outputs(&outputs).is_none());
+    }
+
+    #[test]
+    fn test_lcg_crack() {
+        let mut rng = MT19937Rng::from_seed(1_u32);
+        for &m in [1_u64 << 32, 2147483647, 1000003, 1 << 20].iter() {
+            // an even multiplier loses a bit of state each step
+            let a = if m.is_power_of_two() { rng.gen_range(0, m / 2) * 2 + 1 } else { rng.gen_range(2, m) };
+            let c = rng.gen_range(0, m);
+            let mut lcg = Lcg::new(a, c, m, rng.gen_range(0, m));
+            let outputs = (0..12).map(|_| lcg.next()).collect::<Vec<_>>();
+            // a power of two modulus can leave parameters the outputs can't
+            // tell apart, but then they agree on what comes next as well
+            let mut cracked = Lcg::crack(&outputs).unwrap();
+            assert!((0..20).all(|_| lcg.next() == cracked.next()));
+        }
+        assert_eq!(None, Lcg::crack(&[1, 2, 3]));
+    }
+
+    #[test]
+    fn test_lcg_crack_multiple_modulus() {
+        // twelve outputs of these also fit 2m or 3m, and the two generators
+        // part ways later; a few more outputs settle it
+        let cases = [(2147483647, 287346202, 1572442175, 729545184),
+                     (1000003, 421208, 477732, 281861),
+                     (1000003, 315993, 311255, 221217),
+                     (1 << 20, 173729, 629064, 145026)];
+        for &(m, a, c, seed) in cases.iter() {
+            let mut lcg = Lcg::new(a, c, m, seed);
+            let outputs = (0..24).map(|_| lcg.next()).collect::<Vec<_>>();
+            assert_eq!(None, Lcg::crack(&outputs[..12]));
+            let mut cracked = Lcg::crack(&outputs).unwrap();
+            assert_eq!(m, cracked.m);
+            assert!((0..20).all(|_| lcg.next() == cracked.next()));
+        }
+    }
+
+    #[test]
+    fn test_java() {
+        for (header, values) in read_data("java.txt") {
+            let args = header.split(' ').collect::<Vec<_>>();
+            let mut rng = JavaRandom::new(args[1].parse().unwrap());
+            match args[2] {
+                "nextInt" => {
+                    let expected = numbers::<i32>(&values);
+                    let got = expected.iter().map(|_| rng.next_int()).collect::<Vec<_>>();
+                    assert_eq!(expected, got);
+                }
+                "nextInt(16)" | "nextInt(100)" => {
+                    let bound = args[2][8..args[2].len() - 1].parse().unwrap();
+                    let expected = numbers::<i32>(&values);
+                    let got = expected.iter().map(|_| rng.next_int_bound(bound)).collect::<Vec<_>>();
+                    assert_eq!(expected, got);
+                }
+                "nextLong" => {
+                    let expected = numbers::<i64>(&values);
+                    let got = expected.iter().map(|_| rng.next_long()).collect::<Vec<_>>();
+                    assert_eq!(expected, got);
+                }
+                "nextDouble" => {
+                    let expected = numbers::<f64>(&values);
+                    let got = expected.iter().map(|_| rng.next_double()).collect::<Vec<_>>();
+                    assert_eq!(expected, got);
+                }
+                _ => panic!("unknown section {}", header),
+            }
+        }
+
+        // gen::<f64>() is nextDouble()
+        let mut rng = JavaRandom::from_seed(0);
+        assert_eq!(0.730967787376657, rng.gen::<f64>());
+    }
+
+    #[test]
+    fn test_java_crack() {
+        let data = read_data("java.txt");
+
+        // new Random(42), then two nextInt()s: back to the seed
+        let ints = numbers::<i32>(&data[0].1);
+        let mut rng = JavaRandom::crack_next_int(&ints[..2]).unwrap();
+        assert!(ints[2..].iter().all(|&o| rng.next_int() == o));
+        rng.rewind(ints.len());
+        assert_eq!(JavaRandom::new(42), rng);
+
+        let doubles = numbers::<f64>(&data[4].1);
+        let mut rng = JavaRandom::crack_next_double(doubles[0]).unwrap();
+        assert!(doubles[1..].iter().all(|&o| rng.next_double() == o));
+
+        // nextInt(16) leaks only 4 bits per call
+        let nibbles = numbers::<u32>(&data[1].1);
+        let mut rng = JavaRandom::crack_top_bits(&nibbles[..16], 4).unwrap();
+        assert!(nibbles[16..].iter().all(|&o| rng.next_int_bound(16) as u32 == o));
+        rng.rewind(nibbles.len());
+        assert_eq!(JavaRandom::new(1337), rng);
+    }
+
+    #[test]
+    fn test_java_crack_top_bits() {
+        let mut rng = thread_rng();
+        for &(bits, n) in [(16, 5), (12, 8), (8, 10)].iter() {
+            let mut java = JavaRandom::new(rng.gen());
+            let outputs = (0..n).map(|_| java.next(bits) as u32).collect::<Vec<_>>();
+            let mut cracked = JavaRandom::crack_top_bits(&outputs, bits).unwrap();
+            assert_eq!(java.next_long(), cracked.next_long());
+        }
+    }
+
+    #[test]
+    fn test_msvc() {
+        let data = read_data("msvc.txt");
+        let expected = numbers::<u32>(&data[0].1);
+        let mut rng = MsvcRand::new(1);
+        assert_eq!(expected, expected.iter().map(|_| rng.rand()).collect::<Vec<_>>());
+
+        let mut rng = MsvcRand::crack(&expected[..3]).unwrap();
+        assert!(expected[3..].iter().all(|&o| rng.rand() == o));
+
+        let mut msvc = MsvcRand::new(thread_rng().gen());
+        let outputs = (0..4).map(|_| msvc.rand()).collect::<Vec<_>>();
+        let mut cracked = MsvcRand::crack(&outputs).unwrap();
+        assert_eq!(msvc.next_u32(), cracked.next_u32());
+    }
+}
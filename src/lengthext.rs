@@ -0,0 +1,166 @@
+use std::str::FromStr;
+
+use rust_crypto::digest::Digest;
+
+use md4::{Md4, Md4State};
+use sha1::{Sha1, Sha1State};
+use util::{read_u32v_be, read_u32v_le, write_u64_be, write_u64_le};
+
+/// Length extension forgeries against secret-prefix MACs
+/// `H(key || msg)`, generalizing the p29/p30 attacks.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Algorithm {
+    Sha1,
+    Md4,
+}
+
+impl Algorithm {
+    pub fn mac_bytes(&self) -> usize {
+        match *self {
+            Algorithm::Sha1 => 20,
+            Algorithm::Md4 => 16,
+        }
+    }
+
+    /// The MD padding appended to a message of `len` bytes: `0x80`, zeros up
+    /// to 56 mod 64, then the bit length (big endian for SHA-1, little endian
+    /// for MD4).
+    pub fn padding(&self, len: usize) -> Vec<u8> {
+        let zeros = (119 - len % 64) % 64;
+        let mut pad = vec![0_u8; zeros + 9];
+        pad[0] = 0x80;
+        let bits = (len as u64) << 3;
+        match *self {
+            Algorithm::Sha1 => write_u64_be(&mut pad[zeros + 1..], bits),
+            Algorithm::Md4 => write_u64_le(&mut pad[zeros + 1..], bits),
+        }
+        pad
+    }
+}
+
+impl FromStr for Algorithm {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Algorithm, String> {
+        match &s.to_lowercase()[..] {
+            "sha1" => Ok(Algorithm::Sha1),
+            "md4" => Ok(Algorithm::Md4),
+            _ => Err(format!("unsupported algorithm: {}", s)),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct Forgery {
+    pub key_len: usize,
+    /// `msg || glue padding || append`; the key is still missing from the
+    /// front.
+    pub message: Vec<u8>,
+    pub mac: Vec<u8>,
+}
+
+/// Forge a MAC for `msg || padding || append` from the MAC of `msg`,
+/// assuming the secret key is `key_len` bytes long.
+pub fn forge(alg: Algorithm, msg: &[u8], mac: &[u8], append: &[u8], key_len: usize) -> Forgery {
+    assert_eq!(alg.mac_bytes(), mac.len(), "MAC has the wrong length for {:?}", alg);
+
+    let glue = alg.padding(key_len + msg.len());
+    let processed = (key_len + msg.len() + glue.len()) as u64;
+    let mut forged_mac = vec![0_u8; alg.mac_bytes()];
+
+    match alg {
+        Algorithm::Sha1 => {
+            let mut state = Sha1State { state: [0; 5] };
+            read_u32v_be(&mut state.state, mac);
+            let mut m = Sha1::from_state(processed, state);
+            m.input(append);
+            m.result(&mut forged_mac);
+        },
+        Algorithm::Md4 => {
+            let mut state = Md4State { state: [0; 4] };
+            read_u32v_le(&mut state.state, mac);
+            let mut m = Md4::from_state(processed, state);
+            m.input(append);
+            m.result(&mut forged_mac);
+        },
+    }
+
+    let mut message = msg.to_vec();
+    message.extend_from_slice(&glue);
+    message.extend_from_slice(append);
+
+    Forgery {
+        key_len: key_len,
+        message: message,
+        mac: forged_mac,
+    }
+}
+
+/// Percent-encode everything outside the URL unreserved characters.
+pub fn url_escape(data: &[u8]) -> String {
+    let mut out = String::with_capacity(data.len());
+    for &b in data {
+        match b {
+            b'A'...b'Z' | b'a'...b'z' | b'0'...b'9' | b'-' | b'_' | b'.' | b'~' => {
+                out.push(b as char)
+            },
+            _ => out.push_str(&format!("%{:02x}", b)),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{Rng, weak_rng};
+    use rust_crypto::digest::Digest;
+
+    use md4::Md4;
+    use sha1::Sha1;
+    use super::{forge, url_escape, Algorithm};
+
+    fn mac<D: Digest>(mut h: D, key: &[u8], msg: &[u8]) -> Vec<u8> {
+        let mut out = vec![0_u8; h.output_bytes()];
+        h.input(key);
+        h.input(msg);
+        h.result(&mut out);
+        out
+    }
+
+    #[test]
+    fn test_padding() {
+        for len in 0..200 {
+            for &alg in [Algorithm::Sha1, Algorithm::Md4].iter() {
+                let pad = alg.padding(len);
+                assert_eq!(0, (len + pad.len()) % 64);
+                assert!(pad.len() >= 9 && pad.len() <= 72);
+            }
+        }
+    }
+
+    #[test]
+    fn test_forge() {
+        let mut rng = weak_rng();
+        let msg = b"comment1=cooking%20MCs;userdata=foo;comment2=%20like%20a%20pound%20of%20bacon";
+        let append = b";admin=true";
+
+        // cover key lengths that push the glue padding into a second block
+        for key_len in 0..64 {
+            let key = rng.gen_iter::<u8>().take(key_len).collect::<Vec<u8>>();
+
+            let f = forge(Algorithm::Sha1, msg, &mac(Sha1::new(), &key, msg), append, key_len);
+            assert!(f.message.ends_with(append));
+            assert_eq!(mac(Sha1::new(), &key, &f.message), f.mac);
+
+            let f = forge(Algorithm::Md4, msg, &mac(Md4::new(), &key, msg), append, key_len);
+            assert_eq!(mac(Md4::new(), &key, &f.message), f.mac);
+        }
+    }
+
+    #[test]
+    fn test_url_escape() {
+        assert_eq!("a%3bb%3dc%80%00~", url_escape(b"a;b=c\x80\x00~"));
+        assert_eq!(Ok(Algorithm::Md4), "MD4".parse());
+        assert!("sha256".parse::<Algorithm>().is_err());
+    }
+}
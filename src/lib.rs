@@ -9,13 +9,34 @@ extern crate rustc_serialize as serialize;
 extern crate hyper;
 extern crate num;
 
+pub mod cavp;
+pub mod collision;
 pub mod crypto;
+pub mod drbg;
+pub mod encoding;
+pub mod expandable;
 pub mod freq;
+pub mod gf2;
+pub mod hashsig;
+pub mod herding;
+pub mod lcg;
+pub mod lengthext;
 pub mod math;
 pub mod md4;
+pub mod mtsolve;
+pub mod otp;
+pub mod pyrandom;
+pub mod randtest;
+pub mod seedsearch;
+pub mod seedtable;
 pub mod sha1;
+pub mod sha3;
+pub mod tokens;
+pub mod toyhash;
 pub mod util;
 pub mod vector;
+pub mod wycheproof;
+pub mod xorshift;
 
 mod set1;
 mod set2;
@@ -1,4 +1,5 @@
 use num::{Integer, Zero, One, BigInt, BigUint, FromPrimitive, ToPrimitive};
+use num::rational::BigRational;
 
 pub trait ModInv: Integer {
     fn modinv(&self, m: &Self) -> Self;
@@ -59,6 +60,115 @@ fn test_modinv() {
     assert_eq!(15, r);
 }
 
+fn dot(a: &[BigRational], b: &[BigRational]) -> BigRational {
+    a.iter().zip(b).fold(BigRational::zero(), |acc, (x, y)| acc + x * y)
+}
+
+fn to_rational(v: &[BigInt]) -> Vec<BigRational> {
+    v.iter().map(|x| BigRational::from_integer(x.clone())).collect()
+}
+
+/// Gram-Schmidt orthogonalization of the rows of `basis`: the orthogonal
+/// vectors and the coefficients `mu[i][j] = <b_i, b*_j> / <b*_j, b*_j>`.
+fn gram_schmidt(basis: &[Vec<BigInt>]) -> (Vec<Vec<BigRational>>, Vec<Vec<BigRational>>) {
+    let n = basis.len();
+    let mut ortho: Vec<Vec<BigRational>> = Vec::with_capacity(n);
+    let mut mu = vec![vec![BigRational::zero(); n]; n];
+    for i in 0..n {
+        let b = to_rational(&basis[i]);
+        let mut v = b.clone();
+        for j in 0..i {
+            let norm = dot(&ortho[j], &ortho[j]);
+            if norm.is_zero() {
+                continue;
+            }
+            mu[i][j] = dot(&b, &ortho[j]) / norm;
+            for (x, y) in v.iter_mut().zip(&ortho[j]) {
+                *x = &*x - &mu[i][j] * y;
+            }
+        }
+        ortho.push(v);
+    }
+    (ortho, mu)
+}
+
+/// LLL-reduce the rows of `basis` (with delta = 3/4), which must be
+/// linearly independent. Exact arithmetic throughout, so only meant for
+/// the small lattices of truncated-output attacks.
+pub fn lll_reduce(basis: &[Vec<BigInt>]) -> Vec<Vec<BigInt>> {
+    let n = basis.len();
+    let mut b = basis.to_vec();
+    if n < 2 {
+        return b;
+    }
+    let (ortho, mut mu) = gram_schmidt(&b);
+    let mut norms = ortho.iter().map(|v| dot(v, v)).collect::<Vec<_>>();
+    let delta = BigRational::new(BigInt::from(3), BigInt::from(4));
+
+    let mut k = 1;
+    while k < n {
+        // size reduce b_k
+        for j in (0..k).rev() {
+            let q = mu[k][j].round().to_integer();
+            if q.is_zero() {
+                continue;
+            }
+            let bj = b[j].clone();
+            for (x, y) in b[k].iter_mut().zip(&bj) {
+                *x = &*x - &q * y;
+            }
+            let qr = BigRational::from_integer(q);
+            for l in 0..j {
+                let t = &qr * &mu[j][l];
+                mu[k][l] = &mu[k][l] - t;
+            }
+            mu[k][j] = &mu[k][j] - &qr;
+        }
+
+        let m = mu[k][k - 1].clone();
+        if norms[k] >= (&delta - &m * &m) * &norms[k - 1] {
+            k += 1;
+            continue;
+        }
+
+        // swap b_k and b_{k-1}, updating the Gram-Schmidt data in place
+        b.swap(k, k - 1);
+        let big_b = &norms[k] + &m * &m * &norms[k - 1];
+        mu[k][k - 1] = &m * &norms[k - 1] / &big_b;
+        norms[k] = &norms[k - 1] * &norms[k] / &big_b;
+        norms[k - 1] = big_b;
+        for j in 0..k - 1 {
+            let t = mu[k][j].clone();
+            mu[k][j] = mu[k - 1][j].clone();
+            mu[k - 1][j] = t;
+        }
+        for i in k + 1..n {
+            let t = mu[i][k].clone();
+            mu[i][k] = &mu[i][k - 1] - &m * &t;
+            mu[i][k - 1] = t + &mu[k][k - 1] * &mu[i][k];
+        }
+        k = if k > 1 { k - 1 } else { 1 };
+    }
+    b
+}
+
+/// A lattice vector close to `target` by Babai's nearest plane algorithm.
+/// Within a factor of `2^(n/2)` of the closest when `basis` is LLL-reduced,
+/// and usually the closest itself when `target` is much nearer to the
+/// lattice than its shortest vectors are long.
+pub fn closest_vector(basis: &[Vec<BigInt>], target: &[BigInt]) -> Vec<BigInt> {
+    let (ortho, _) = gram_schmidt(basis);
+    let mut w = target.to_vec();
+    for i in (0..basis.len()).rev() {
+        let norm = dot(&ortho[i], &ortho[i]);
+        let c = (dot(&to_rational(&w), &ortho[i]) / norm).round().to_integer();
+        for (x, y) in w.iter_mut().zip(&basis[i]) {
+            *x = &*x - &c * y;
+        }
+    }
+    target.iter().zip(&w).map(|(t, x)| t - x).collect()
+}
+
 pub trait ModExp: Integer {
     fn modexp(&self, exp: &Self, m: &Self) -> Self;
 }
@@ -114,3 +224,21 @@ fn test_modexp_negative_base() {
     let r = b.modexp(&e, &m).to_i32().unwrap();
     assert_eq!(31, r);
 }
+
+#[test]
+fn test_lll_reduce() {
+    let basis = [[1, 1, 1], [-1, 0, 2], [3, 5, 6]].iter()
+        .map(|r| r.iter().map(|&x| BigInt::from(x)).collect::<Vec<_>>())
+        .collect::<Vec<_>>();
+    let reduced = lll_reduce(&basis).iter()
+        .map(|r| r.iter().map(|x| x.to_i64().unwrap()).collect::<Vec<_>>())
+        .collect::<Vec<_>>();
+    // the last row could as well be (-1, 0, 2): it's half way between
+    assert_eq!(vec![vec![0, 1, 0], vec![1, 0, 1], vec![-2, 0, 1]], reduced);
+
+    // a lattice point, 4*(0,1,0) + 3*(1,0,1) + 4*(-1,0,2), is its own
+    // closest vector
+    let target = [BigInt::from(-1), BigInt::from(4), BigInt::from(11)];
+    let closest = closest_vector(&lll_reduce(&basis), &target);
+    assert_eq!(target.to_vec(), closest);
+}
@@ -15,7 +15,7 @@ pub struct Md4 {
     state: Md4State,
 }
 
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
 pub struct Md4State {
     pub state: [u32; 4],
 }
@@ -48,6 +48,23 @@ impl Md4 {
             }
         }
     }
+
+    /// The chaining value after the last full block absorbed so far.
+    pub fn state(&self) -> Md4State {
+        self.state
+    }
+
+    /// Like `input`, but pushes the chaining value after every full block
+    /// compressed onto `states`.
+    pub fn input_recording(&mut self, data: &[u8], states: &mut Vec<Md4State>) {
+        let len = &mut self.len;
+        let state = &mut self.state;
+        self.blocks.input(data, |chunk| {
+            *len += 64;
+            state.process(chunk);
+            states.push(*state);
+        });
+    }
 }
 
 impl Digest for Md4 {
@@ -126,7 +143,12 @@ impl Blocks {
 }
 
 impl Md4State {
-    fn process(&mut self, block: &[u8]) {
+    pub fn new() -> Md4State {
+        DEFAULT_STATE
+    }
+
+    /// The raw compression function over a single 64 byte block.
+    pub fn process(&mut self, block: &[u8]) {
         fn f(x: u32, y: u32, z: u32) -> u32 {
             (x & y) | (!x & z)
         }
@@ -200,7 +222,7 @@ impl Clone for Blocks {
 #[cfg(test)]
 mod tests {
     use rust_crypto::digest::Digest;
-    use super::Md4;
+    use super::{Md4, Md4State};
 
     #[test]
     fn test_correctness() {
@@ -225,4 +247,23 @@ mod tests {
             m.reset();
         }
     }
+
+    #[test]
+    fn test_input_recording() {
+        let msg = [0x61_u8; 200];
+        let mut m = Md4::new();
+        let mut states = Vec::new();
+        m.input_recording(&msg, &mut states);
+        assert_eq!(3, states.len());
+
+        let mut state = Md4State::new();
+        for (chunk, recorded) in msg.chunks(64).zip(states.iter()) {
+            state.process(chunk);
+            assert_eq!(&state, recorded);
+        }
+
+        let mut m2 = Md4::from_state(64, states[0]);
+        m2.input(&msg[64..]);
+        assert_eq!(m.result_str(), m2.result_str());
+    }
 }
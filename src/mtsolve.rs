@@ -0,0 +1,372 @@
+use std::fmt;
+use std::num::Wrapping as w;
+
+use rand::Rng;
+
+use gf2::{BitMatrix, BitVec};
+use util::{MT19937Rng, temper};
+
+const N: usize = 624;
+const M: usize = 397;
+const MATRIX_A: u32 = 0x9908B0DF;
+
+/// Unknowns in the model: the 624 state words before the first observed
+/// output.
+const UNKNOWNS: usize = 32 * N;
+
+/// Only the top bit of the oldest word ever reaches an output, so at most
+/// this many of the unknowns can be pinned down.
+pub const STATE_BITS: usize = 19937;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum SolveError {
+    /// The observations pin down at most `rank` of the `STATE_BITS` state
+    /// bits; more outputs (or more bits per output) are needed.
+    Insufficient { rank: usize },
+    /// No state produces every observation, e.g. because an output was
+    /// skipped or misattributed.
+    Inconsistent,
+}
+
+impl fmt::Display for SolveError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            SolveError::Insufficient { rank } => {
+                write!(f, "only {} of {} state bits determined", rank, STATE_BITS)
+            },
+            SolveError::Inconsistent => write!(f, "observations are inconsistent"),
+        }
+    }
+}
+
+/// Clone an `MT19937Rng` from partial outputs: the top bits of each output,
+/// a `gen_range` value, single bytes of a keystream, or any other known
+/// subset of the output bits.
+///
+/// The twist and the tempering are both linear over GF(2), so every output
+/// bit is the XOR of some bits of the state the generator had before the
+/// first observation. The solver tracks each state bit symbolically, as a
+/// vector over those 19968 unknowns, turns every observed bit into one
+/// equation and solves the system with `gf2`. Unlike `MT19937Cloner`, which
+/// needs 624 whole consecutive outputs, any mix of observations works once
+/// they pin down all 19937 bits of state.
+pub struct MT19937Solver {
+    // symbolic x[k..k + N], x[j] at index j % N, each word as 32 bit vectors
+    words: Vec<Vec<BitVec>>,
+    k: usize,
+    // for each output bit, the state word bits XORed into it by tempering
+    temper_bits: Vec<Vec<usize>>,
+    eqs: Vec<BitVec>,
+    rhs: Vec<bool>,
+    outputs: u64,
+}
+
+impl MT19937Solver {
+    pub fn new() -> MT19937Solver {
+        let words = (0..N).map(|j| {
+            (0..32).map(|b| {
+                let mut v = BitVec::zeros(UNKNOWNS);
+                v.set(32 * j + b, true);
+                v
+            }).collect()
+        }).collect();
+        let temper_bits = (0..32)
+            .map(|i| (0..32).filter(|&j| (temper(1 << j) >> i) & 1 == 1).collect())
+            .collect();
+
+        MT19937Solver {
+            words: words,
+            k: 0,
+            temper_bits: temper_bits,
+            eqs: Vec::new(),
+            rhs: Vec::new(),
+            outputs: 0,
+        }
+    }
+
+    // symbolically generate x[k + N] from x[k], x[k + 1] and x[k + M], and
+    // return its slot
+    fn next_word(&mut self) -> usize {
+        let k = self.k;
+        let word = {
+            let hi = &self.words[k % N];
+            let lo = &self.words[(k + 1) % N];
+            let mid = &self.words[(k + M) % N];
+            // y = (x[k] & 0x80000000) | (x[k + 1] & 0x7fffffff)
+            let y = |j: usize| if j == 31 { &hi[31] } else { &lo[j] };
+            (0..32).map(|b| {
+                // x[k + M] ^ (y >> 1) ^ (y & 1) * MATRIX_A
+                let mut v = mid[b].clone();
+                if b < 31 {
+                    v ^= y(b + 1);
+                }
+                if (MATRIX_A >> b) & 1 == 1 {
+                    v ^= y(0);
+                }
+                v
+            }).collect()
+        };
+        self.words[k % N] = word;
+        self.k += 1;
+        self.outputs += 1;
+        k % N
+    }
+
+    /// Observe the next output, of which only the bits set in `mask` are
+    /// known.
+    pub fn push_masked(&mut self, y: u32, mask: u32) {
+        let slot = self.next_word();
+        for i in 0..32 {
+            if (mask >> i) & 1 == 0 {
+                continue;
+            }
+            let mut eq = BitVec::zeros(UNKNOWNS);
+            for &j in &self.temper_bits[i] {
+                eq ^= &self.words[slot][j];
+            }
+            self.eqs.push(eq);
+            self.rhs.push((y >> i) & 1 == 1);
+        }
+    }
+
+    /// Observe a whole output.
+    pub fn push(&mut self, y: u32) {
+        self.push_masked(y, !0);
+    }
+
+    /// Observe the top `bits` bits of the next output, e.g. Python's
+    /// `getrandbits(bits)`.
+    pub fn push_top(&mut self, value: u32, bits: u32) {
+        assert!(bits > 0 && bits <= 32);
+        let shift = 32 - bits;
+        self.push_masked(value << shift, !0 << shift);
+    }
+
+    /// Observe `rng.gen_range(low, low + range)` as `value - low`. Only the
+    /// low bits shared by every multiple of `range` survive the reduction:
+    /// all 32 bits are lost for odd ranges. Assumes the sample was not
+    /// rejected, which happens with probability `(2^32 mod range) / 2^32`.
+    pub fn push_range(&mut self, value: u32, range: u32) {
+        assert!(range > 0 && value < range);
+        let bits = range.trailing_zeros();
+        let mask = if bits >= 32 { !0 } else { (1 << bits) - 1 };
+        self.push_masked(value & mask, mask);
+    }
+
+    /// Skip `n` outputs that were not observed.
+    pub fn skip(&mut self, n: usize) {
+        for _ in 0..n {
+            self.next_word();
+        }
+    }
+
+    /// Equations collected so far: one per observed bit.
+    pub fn equations(&self) -> usize {
+        self.eqs.len()
+    }
+
+    /// Outputs seen or skipped so far.
+    pub fn outputs(&self) -> u64 {
+        self.outputs
+    }
+
+    /// Solve for the state and return a generator positioned just after
+    /// the last output pushed or skipped.
+    pub fn solve(&self) -> Result<MT19937Rng, SolveError> {
+        if self.eqs.len() < STATE_BITS {
+            // can't possibly be enough, don't bother eliminating
+            return Err(SolveError::Insufficient { rank: self.eqs.len() });
+        }
+
+        let mut a = BitMatrix::from_rows(&self.eqs);
+        let mut b = BitVec::from_bools(&self.rhs);
+        let echelon = a.eliminate(Some(&mut b));
+        if echelon.rank() < STATE_BITS {
+            return Err(SolveError::Insufficient { rank: echelon.rank() });
+        }
+        if (echelon.rank()..self.eqs.len()).any(|r| b.get(r)) {
+            return Err(SolveError::Inconsistent);
+        }
+
+        // the 31 unobservable low bits of x[0] stay zero
+        let mut state = [w(0_u32); N];
+        for (r, &c) in echelon.pivots.iter().enumerate() {
+            if b.get(r) {
+                state[c / 32].0 |= 1 << (c % 32);
+            }
+        }
+
+        let mut rng = MT19937Rng::from_state(N, state);
+        for _ in 0..self.outputs {
+            rng.next_u32();
+        }
+        Ok(rng)
+    }
+}
+
+/// Rebuild the generator behind a `util::prng_crypt` ciphertext from known
+/// plaintext, given as `(offset, bytes)` pieces. Each keystream byte is one
+/// byte of an MT output, so about 2500 known bytes are needed, spread over
+/// enough of the message to cover the state; the seed itself never matters.
+///
+/// The returned generator is positioned at the start of the message:
+/// `prng_crypt(&mut rng, ciphertext)` decrypts all of it, and further calls
+/// decrypt the messages encrypted after it.
+pub fn recover_prng_crypt(ciphertext: &[u8], known: &[(usize, &[u8])])
+    -> Result<MT19937Rng, SolveError>
+{
+    let mut keystream = vec![None; ciphertext.len()];
+    for &(offset, plaintext) in known {
+        assert!(offset + plaintext.len() <= ciphertext.len(), "known plaintext out of range");
+        for (i, &p) in plaintext.iter().enumerate() {
+            keystream[offset + i] = Some(ciphertext[offset + i] ^ p);
+        }
+    }
+
+    // PRNGKeystream hands out the bytes of each output little end first
+    let mut solver = MT19937Solver::new();
+    for chunk in keystream.chunks(4) {
+        let (mut y, mut mask) = (0, 0);
+        for (b, k) in chunk.iter().enumerate() {
+            if let Some(k) = *k {
+                y |= (k as u32) << (8 * b);
+                mask |= 0xff << (8 * b);
+            }
+        }
+        solver.push_masked(y, mask);
+    }
+
+    let mut rng = try!(solver.solve());
+    rng.rewind(solver.outputs() as usize);
+    Ok(rng)
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{Rng, SeedableRng};
+
+    use pyrandom::PyRandom;
+    use util::{MT19937Rng, prng_crypt};
+    use super::{MT19937Solver, SolveError, recover_prng_crypt};
+
+    fn assert_clone(rng: &mut MT19937Rng, solver: &MT19937Solver) {
+        let mut clone = solver.solve().unwrap();
+        for _ in 0..1000 {
+            assert_eq!(rng.next_u32(), clone.next_u32());
+        }
+    }
+
+    #[test]
+    fn test_insufficient() {
+        let mut rng = MT19937Rng::from_seed(1234_u32);
+        let mut solver = MT19937Solver::new();
+        for _ in 0..100 {
+            solver.push(rng.next_u32());
+        }
+        assert_eq!(Err(SolveError::Insufficient { rank: 3200 }), solver.solve().map(|_| ()));
+    }
+
+    #[test] #[ignore] // run with --release
+    fn test_full_outputs() {
+        // the same job as MT19937Cloner, but with a gap
+        let mut rng = MT19937Rng::from_seed(0xdeadbeef_u32);
+        let mut solver = MT19937Solver::new();
+        for _ in 0..300 {
+            solver.push(rng.next_u32());
+        }
+        rng.next_u32();
+        solver.skip(1);
+        // the skipped word is only pinned down by the two words it feeds
+        // into 623 and 624 outputs later
+        for _ in 0..650 {
+            solver.push(rng.next_u32());
+        }
+        assert_clone(&mut rng, &solver);
+    }
+
+    #[test] #[ignore] // run with --release
+    fn test_python_getrandbits() {
+        // a service handing out random.getrandbits(8) values
+        let mut py = PyRandom::seed_u64(31337);
+        let mut solver = MT19937Solver::new();
+        for _ in 0..2600 {
+            solver.push_top(py.getrandbits_u64(8) as u32, 8);
+        }
+        assert_clone(&mut py.mt, &solver);
+    }
+
+    #[test] #[ignore] // run with --release
+    fn test_gen_range_and_missing_bits() {
+        let mut rng = MT19937Rng::from_seed(42_u32);
+        let mut solver = MT19937Solver::new();
+
+        // dice rolls only leak their parity
+        for _ in 0..10 {
+            let roll = rng.gen_range(0, 6);
+            solver.push_range(roll, 6);
+        }
+        assert_eq!(10, solver.equations());
+
+        // 4 bits per card drawn from a 16 card deck
+        for _ in 0..4000 {
+            let card = rng.gen_range(0, 16);
+            solver.push_range(card, 16);
+        }
+        assert_eq!(Err(SolveError::Insufficient { rank: 16010 }), solver.solve().map(|_| ()));
+
+        // low bits alone are far from independent, so add outputs with a
+        // known-missing middle byte
+        for _ in 0..800 {
+            solver.push_masked(rng.next_u32() & 0xff00ffff, 0xff00ffff);
+        }
+        assert_clone(&mut rng, &solver);
+    }
+
+    #[test] #[ignore] // run with --release
+    fn test_inconsistent() {
+        let mut rng = MT19937Rng::from_seed(7_u32);
+        let mut solver = MT19937Solver::new();
+        for _ in 0..300 {
+            solver.push(rng.next_u32());
+        }
+        // an output dropped without telling the solver
+        rng.next_u32();
+        for _ in 0..350 {
+            solver.push(rng.next_u32());
+        }
+        assert_eq!(Err(SolveError::Inconsistent), solver.solve().map(|_| ()));
+    }
+
+    #[test]
+    fn test_prng_crypt_not_enough_plaintext() {
+        let mut rng = MT19937Rng::from_seed(5_u32);
+        let ctxt = prng_crypt(&mut rng, &[b'A'; 4000]);
+        let known = [(0, &[b'A'; 1000][..])];
+        assert_eq!(Err(SolveError::Insufficient { rank: 8000 }),
+                   recover_prng_crypt(&ctxt, &known).map(|_| ()));
+    }
+
+    #[test] #[ignore] // run with --release
+    fn test_prng_crypt() {
+        // a 128-bit seed: no brute force as in set3::p24
+        let mut rng = MT19937Rng::from_seed_array(&[0x01234567, 0x89abcdef, 0xdeadbeef, 0x31337]);
+
+        // a known header and footer around a secret body
+        let header = (0..1700).map(|i| b"GET /index.html HTTP/1.1\r\n"[i % 26]).collect::<Vec<u8>>();
+        let body = (0..20)
+            .flat_map(|_| b"token=cc2f8b3a1d5e4f60; admin=false".iter().cloned())
+            .collect::<Vec<u8>>();
+        let footer = vec![b' '; 1000];
+        let mut msg = header.clone();
+        msg.extend_from_slice(&body);
+        msg.extend_from_slice(&footer);
+
+        let ctxt = prng_crypt(&mut rng, &msg);
+        let later = prng_crypt(&mut rng, b"the next message, all secret");
+
+        let known = [(0, &header[..]), (header.len() + body.len(), &footer[..])];
+        let mut clone = recover_prng_crypt(&ctxt, &known).unwrap();
+        assert_eq!(msg, prng_crypt(&mut clone, &ctxt));
+        assert_eq!(b"the next message, all secret".to_vec(), prng_crypt(&mut clone, &later));
+    }
+}
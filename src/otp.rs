@@ -0,0 +1,307 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use rust_crypto::hmac::Hmac;
+use rust_crypto::mac::Mac;
+
+use sha1::Sha1;
+use util::write_u64_be;
+
+/// RFC 4226 dynamic truncation: the low nibble of the last byte picks four
+/// bytes of the MAC, read big endian with the top bit cleared.
+pub fn dynamic_truncate(mac: &[u8]) -> u32 {
+    let offset = (mac[mac.len() - 1] & 0x0f) as usize;
+    ((mac[offset] as u32 & 0x7f) << 24) |
+    ((mac[offset + 1] as u32) << 16) |
+    ((mac[offset + 2] as u32) << 8) |
+    (mac[offset + 3] as u32)
+}
+
+/// HOTP value for `counter`: HMAC-SHA1 of the big endian counter, truncated
+/// to `digits` decimal digits.
+pub fn hotp(key: &[u8], counter: u64, digits: u32) -> u32 {
+    assert!(digits >= 1 && digits <= 9, "digits must be between 1 and 9");
+    let mut msg = [0_u8; 8];
+    write_u64_be(&mut msg, counter);
+
+    let mut hmac = Hmac::new(Sha1::new(), key);
+    hmac.input(&msg);
+    let mac = hmac.result();
+    dynamic_truncate(mac.code()) % 10_u32.pow(digits)
+}
+
+/// Format a code with its leading zeros.
+pub fn format_code(code: u32, digits: u32) -> String {
+    format!("{:01$}", code, digits as usize)
+}
+
+/// Counter-based one-time passwords (RFC 4226) as seen by the server.
+pub struct Hotp {
+    key: Vec<u8>,
+    digits: u32,
+    /// The next counter value the server expects.
+    pub counter: u64,
+    /// How many counter values past `counter` a code may come from.
+    pub look_ahead: u64,
+}
+
+impl Hotp {
+    pub fn new(key: &[u8], digits: u32) -> Hotp {
+        Hotp {
+            key: key.to_vec(),
+            digits: digits,
+            counter: 0,
+            look_ahead: 10,
+        }
+    }
+
+    pub fn generate(&self, counter: u64) -> u32 {
+        hotp(&self.key, counter, self.digits)
+    }
+
+    /// Accept a code from anywhere in the look-ahead window, and move the
+    /// counter past it so it can't be used again.
+    pub fn verify(&mut self, code: u32) -> bool {
+        for c in self.counter..self.counter + self.look_ahead + 1 {
+            if self.generate(c) == code {
+                self.counter = c + 1;
+                return true;
+            }
+        }
+        false
+    }
+
+    /// Resynchronize a token that drifted outside the look-ahead window
+    /// (RFC 4226 section 7.4): two consecutive codes are searched for within
+    /// the larger `window`.
+    pub fn resync(&mut self, first: u32, second: u32, window: u64) -> bool {
+        for c in self.counter..self.counter + window + 1 {
+            if self.generate(c) == first && self.generate(c + 1) == second {
+                self.counter = c + 2;
+                return true;
+            }
+        }
+        false
+    }
+}
+
+/// Source of the current Unix time in seconds.
+pub trait Clock {
+    fn now(&self) -> u64;
+}
+
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> u64 {
+        SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs()
+    }
+}
+
+impl<F: Fn() -> u64> Clock for F {
+    fn now(&self) -> u64 {
+        self()
+    }
+}
+
+/// Time-based one-time passwords (RFC 6238): HOTP with the counter set to
+/// the number of `step` second intervals since `t0`.
+pub struct Totp<C> {
+    key: Vec<u8>,
+    digits: u32,
+    step: u64,
+    t0: u64,
+    clock: C,
+    /// How many steps before or after the current one a code may come from.
+    pub window: u64,
+    /// Steps the client's clock is known to be ahead (or behind) by.
+    pub drift: i64,
+    // the last step a code was accepted for, so codes can't be replayed
+    last: Option<u64>,
+}
+
+impl<C: Clock> Totp<C> {
+    /// The RFC defaults: 30 second steps from the Unix epoch, accepting one
+    /// step of skew either way.
+    pub fn new(key: &[u8], digits: u32, clock: C) -> Totp<C> {
+        Totp::with_step(key, digits, 30, 0, clock)
+    }
+
+    pub fn with_step(key: &[u8], digits: u32, step: u64, t0: u64, clock: C) -> Totp<C> {
+        assert!(step > 0);
+        Totp {
+            key: key.to_vec(),
+            digits: digits,
+            step: step,
+            t0: t0,
+            clock: clock,
+            window: 1,
+            drift: 0,
+            last: None,
+        }
+    }
+
+    fn time_step(&self, time: u64) -> u64 {
+        time.saturating_sub(self.t0) / self.step
+    }
+
+    pub fn at(&self, time: u64) -> u32 {
+        hotp(&self.key, self.time_step(time), self.digits)
+    }
+
+    pub fn generate(&self) -> u32 {
+        self.at(self.clock.now())
+    }
+
+    /// Accept a code from within `window` steps of the current step
+    /// (corrected for the known drift). The drift is updated to wherever the
+    /// code matched, and older steps are rejected from then on.
+    pub fn verify(&mut self, code: u32) -> bool {
+        let current = self.time_step(self.clock.now()) as i64 + self.drift;
+        let window = self.window as i64;
+        for delta in 0..window + 1 {
+            for &offset in [delta, -delta].iter() {
+                let step = current + offset;
+                if step < 0 || self.last.map_or(false, |last| step as u64 <= last) {
+                    continue;
+                }
+                if hotp(&self.key, step as u64, self.digits) == code {
+                    self.drift += offset;
+                    self.last = Some(step as u64);
+                    return true;
+                }
+                if delta == 0 {
+                    break;
+                }
+            }
+        }
+        false
+    }
+
+    /// Re-learn the drift of a client whose clock is off by more than the
+    /// verification window, from two consecutive codes within `window`
+    /// steps of now. Like `verify`, it won't go back to a step a code was
+    /// already accepted for.
+    pub fn resync(&mut self, first: u32, second: u32, window: u64) -> bool {
+        let current = self.time_step(self.clock.now()) as i64;
+        let window = window as i64;
+        for offset in -window..window + 1 {
+            let step = current + offset;
+            if step < 0 || self.last.map_or(false, |last| step as u64 <= last) {
+                continue;
+            }
+            if hotp(&self.key, step as u64, self.digits) == first &&
+               hotp(&self.key, step as u64 + 1, self.digits) == second {
+                self.drift = offset + 1;
+                self.last = Some(step as u64 + 1);
+                return true;
+            }
+        }
+        false
+    }
+}
+
+/// Try every code of `digits` digits against `oracle` until it accepts one.
+/// Returns the accepted code and the number of guesses it took. Without rate
+/// limiting a 6 digit code falls in at most a million requests, and a
+/// look-ahead window of `w` codes divides the expected work by about `w`.
+pub fn brute_force<F: FnMut(u32) -> bool>(digits: u32, mut oracle: F) -> Option<(u32, u64)> {
+    for code in 0..10_u32.pow(digits) {
+        if oracle(code) {
+            return Some((code, code as u64 + 1));
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+
+    use super::{brute_force, format_code, hotp, Hotp, Totp};
+
+    const KEY: &'static [u8] = b"12345678901234567890";
+
+    #[test]
+    fn test_hotp_rfc4226() {
+        let expected = [755224, 287082, 359152, 969429, 338314,
+                        254676, 287922, 162583, 399871, 520489];
+        for (counter, &code) in expected.iter().enumerate() {
+            assert_eq!(code, hotp(KEY, counter as u64, 6));
+        }
+    }
+
+    #[test]
+    fn test_totp_rfc6238() {
+        let expected = [
+            (59, "94287082"),
+            (1111111109, "07081804"),
+            (1111111111, "14050471"),
+            (1234567890, "89005924"),
+            (2000000000, "69279037"),
+            (20000000000, "65353130"),
+        ];
+        let totp = Totp::new(KEY, 8, || 0);
+        for &(time, code) in expected.iter() {
+            assert_eq!(code, format_code(totp.at(time), 8));
+        }
+    }
+
+    #[test]
+    fn test_hotp_verify() {
+        let mut server = Hotp::new(KEY, 6);
+        assert!(server.verify(hotp(KEY, 0, 6)));
+        assert_eq!(1, server.counter);
+        // replay
+        assert!(!server.verify(hotp(KEY, 0, 6)));
+
+        // the client pressed the button a few times without logging in
+        assert!(server.verify(hotp(KEY, 5, 6)));
+        assert_eq!(6, server.counter);
+
+        // too far ahead for the look-ahead window, but resync catches up
+        assert!(!server.verify(hotp(KEY, 50, 6)));
+        assert!(server.resync(hotp(KEY, 50, 6), hotp(KEY, 51, 6), 100));
+        assert_eq!(52, server.counter);
+        assert!(server.verify(hotp(KEY, 52, 6)));
+    }
+
+    #[test]
+    fn test_totp_verify() {
+        let now = Cell::new(1111111111_u64);
+        let client = Totp::new(KEY, 6, || now.get());
+        let mut server = Totp::new(KEY, 6, || now.get());
+
+        let code = client.generate();
+        assert!(server.verify(code));
+        assert!(!server.verify(code), "codes can't be replayed");
+
+        // one step of skew either way is fine
+        now.set(now.get() + 30);
+        assert!(server.verify(client.at(now.get() + 30)));
+        assert_eq!(1, server.drift);
+
+        // a client that's five minutes slow needs a resync
+        now.set(now.get() + 600);
+        let slow = now.get() - 300;
+        assert!(!server.verify(client.at(slow)));
+        assert!(server.resync(client.at(slow), client.at(slow + 30), 20));
+        assert_eq!(-9, server.drift);
+        now.set(now.get() + 30);
+        assert!(server.verify(client.at(slow + 60)));
+
+        // replaying the pair doesn't wind the last accepted step back
+        assert!(!server.resync(client.at(slow), client.at(slow + 30), 20));
+        assert!(!server.resync(client.at(slow + 30), client.at(slow + 60), 20));
+        assert!(!server.verify(client.at(slow + 60)), "codes can't be replayed");
+        assert_eq!(-9, server.drift);
+    }
+
+    #[test]
+    fn test_brute_force() {
+        // a 4 digit code with no rate limiting
+        let mut server = Hotp::new(b"a short-lived secret", 4);
+        let (code, guesses) = brute_force(4, |c| server.verify(c)).unwrap();
+        assert_eq!(hotp(b"a short-lived secret", server.counter - 1, 4), code);
+        assert!(guesses <= 10000);
+    }
+}
@@ -0,0 +1,126 @@
+use num::{BigUint, FromPrimitive, ToPrimitive};
+use rand::Rng;
+
+use util::MT19937Rng;
+
+/// CPython's `random.Random`: MT19937 seeded with `init_by_array`, plus the
+/// way Python turns its 32-bit outputs into integers and floats. Tokens from
+/// `random.getrandbits(128)` or `random.random()` in a Python service can be
+/// reproduced (or predicted, after cloning the MT state) bit for bit.
+pub struct PyRandom {
+    pub mt: MT19937Rng,
+}
+
+impl PyRandom {
+    /// `random.seed(n)` for a non-negative int: the absolute value is split
+    /// into 32-bit words, least significant first.
+    pub fn seed(n: &BigUint) -> PyRandom {
+        let bytes = n.to_bytes_le();
+        let mut key = bytes.chunks(4)
+            .map(|c| c.iter().rev().fold(0_u32, |acc, &b| (acc << 8) | b as u32))
+            .collect::<Vec<_>>();
+        // to_bytes_le gives [0] for zero, but trailing zero words would change
+        // the seed
+        while key.len() > 1 && key[key.len() - 1] == 0 {
+            key.pop();
+        }
+        PyRandom { mt: MT19937Rng::from_seed_array(&key) }
+    }
+
+    pub fn seed_u64(n: u64) -> PyRandom {
+        PyRandom::seed(&BigUint::from_u64(n).unwrap())
+    }
+
+    /// Wrap a generator, e.g. one cloned from observed outputs.
+    pub fn from_mt(mt: MT19937Rng) -> PyRandom {
+        PyRandom { mt: mt }
+    }
+
+    /// `random.getrandbits(k)`: whole 32-bit outputs, least significant word
+    /// first, with the last one cut down to its top bits.
+    pub fn getrandbits(&mut self, k: usize) -> BigUint {
+        assert!(k > 0, "number of bits must be greater than zero");
+        let mut words = Vec::with_capacity((k + 31) / 32);
+        let mut left = k;
+        while left > 0 {
+            let x = self.mt.next_u32();
+            if left < 32 {
+                words.push(x >> (32 - left));
+                left = 0;
+            } else {
+                words.push(x);
+                left -= 32;
+            }
+        }
+        BigUint::new(words)
+    }
+
+    /// `getrandbits(k)` for `k <= 64`.
+    pub fn getrandbits_u64(&mut self, k: usize) -> u64 {
+        assert!(k > 0 && k <= 64);
+        self.getrandbits(k).to_u64().unwrap()
+    }
+
+    /// `random.random()`: a 53-bit float from two outputs.
+    pub fn random(&mut self) -> f64 {
+        let a = (self.mt.next_u32() >> 5) as f64;
+        let b = (self.mt.next_u32() >> 6) as f64;
+        (a * 67108864.0 + b) * (1.0 / 9007199254740992.0)
+    }
+
+    /// `random.randrange(n)` (and `randint`, `choice`, ...) via
+    /// `_randbelow`: rejection sampling on `getrandbits(n.bit_length())`.
+    pub fn randbelow(&mut self, n: u64) -> u64 {
+        assert!(n > 0);
+        let k = 64 - n.leading_zeros() as usize;
+        loop {
+            let r = self.getrandbits_u64(k);
+            if r < n {
+                return r;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use num::{BigUint, FromPrimitive, One};
+    use rand::Rng;
+
+    use super::PyRandom;
+
+    // expected values come from CPython 3
+    #[test]
+    fn test_seed_int() {
+        let mut r = PyRandom::seed_u64(42);
+        assert_eq!(2746317213, r.mt.next_u32());
+        assert_eq!(478163327, r.mt.next_u32());
+
+        let mut r = PyRandom::seed_u64(0);
+        assert_eq!(3626764237, r.mt.next_u32());
+
+        // random.seed(2**80 + 12345)
+        let n = (BigUint::one() << 80) + BigUint::from_u32(12345).unwrap();
+        let mut r = PyRandom::seed(&n);
+        assert_eq!(3137230745, r.mt.next_u32());
+        assert_eq!(1980212638, r.mt.next_u32());
+        assert_eq!(0.4197013131370422, r.random());
+    }
+
+    #[test]
+    fn test_getrandbits_and_random() {
+        let mut r = PyRandom::seed_u64(42);
+        r.getrandbits(32);
+        r.getrandbits(32);
+        assert_eq!(107420369, r.getrandbits_u64(32));
+        assert_eq!(0.7415504997598329, r.random());
+        assert_eq!(7, r.getrandbits_u64(5));
+        assert_eq!("869570991858821387998", r.getrandbits(70).to_string());
+        assert_eq!(12482902213779070887, r.getrandbits_u64(64));
+        assert_eq!(776646, r.randbelow(1000000));
+
+        let mut r = PyRandom::seed_u64(12345);
+        assert_eq!(0.41661987254534116, r.random());
+        assert_eq!(0.010169169457068361, r.random());
+    }
+}
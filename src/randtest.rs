@@ -0,0 +1,403 @@
+use std::f64::consts::{LN_2, SQRT_2};
+
+use util::count_bits;
+
+// Tests from NIST SP 800-22 rev 1a, "A Statistical Test Suite for Random
+// and Pseudorandom Number Generators for Cryptographic Applications". Each
+// reads a byte stream most significant bit first and returns a p-value: the
+// chance a truly random sequence would look at least this far off. Anything
+// under `ALPHA` is a failure.
+
+/// The significance level SP 800-22 recommends.
+pub const ALPHA: f64 = 0.01;
+
+fn to_bits<I: IntoIterator<Item = u8>>(bytes: I) -> Vec<u8> {
+    let mut bits = Vec::new();
+    for b in bytes {
+        for i in (0..8).rev() {
+            bits.push((b >> i) & 1);
+        }
+    }
+    bits
+}
+
+/// Frequency (monobit) test: are there as many ones as zeros?
+pub fn monobit<I: IntoIterator<Item = u8>>(bytes: I) -> f64 {
+    let (mut ones, mut n) = (0, 0);
+    for b in bytes {
+        ones += count_bits(b as u32) as usize;
+        n += 8;
+    }
+    monobit_p(ones, n)
+}
+
+fn monobit_p(ones: usize, n: usize) -> f64 {
+    let s = 2.0 * ones as f64 - n as f64;
+    erfc(s.abs() / (n as f64).sqrt() / SQRT_2)
+}
+
+/// Frequency test within blocks: the monobit test on each `m` bit block.
+/// Trailing bits that don't fill a block are ignored.
+pub fn block_frequency<I: IntoIterator<Item = u8>>(bytes: I, m: usize) -> f64 {
+    if m % 8 == 0 {
+        // whole bytes per block, so count them directly
+        let bytes = bytes.into_iter().collect::<Vec<_>>();
+        let blocks = bytes.chunks(m / 8)
+            .filter(|c| c.len() == m / 8)
+            .map(|c| c.iter().map(|&b| count_bits(b as u32) as usize).sum())
+            .collect::<Vec<_>>();
+        block_frequency_p(&blocks, m)
+    } else {
+        block_frequency_bits(&to_bits(bytes), m)
+    }
+}
+
+fn block_frequency_bits(bits: &[u8], m: usize) -> f64 {
+    let blocks = bits.chunks(m)
+        .filter(|c| c.len() == m)
+        .map(|c| c.iter().map(|&b| b as usize).sum())
+        .collect::<Vec<_>>();
+    block_frequency_p(&blocks, m)
+}
+
+fn block_frequency_p(ones: &[usize], m: usize) -> f64 {
+    let chi2 = 4.0 * m as f64 *
+               ones.iter()
+        .map(|&c| (c as f64 / m as f64 - 0.5).powi(2))
+        .sum::<f64>();
+    igamc(ones.len() as f64 / 2.0, chi2 / 2.0)
+}
+
+/// Runs test: does the sequence switch between zeros and ones as often as
+/// it should? Sequences too unbalanced for that to mean anything get 0.
+pub fn runs<I: IntoIterator<Item = u8>>(bytes: I) -> f64 {
+    runs_bits(&to_bits(bytes))
+}
+
+fn runs_bits(bits: &[u8]) -> f64 {
+    let n = bits.len() as f64;
+    let pi = bits.iter().filter(|&&b| b == 1).count() as f64 / n;
+    if (pi - 0.5).abs() >= 2.0 / n.sqrt() {
+        return 0.0;
+    }
+    let v = 1 + bits.windows(2).filter(|w| w[0] != w[1]).count();
+    let q = pi * (1.0 - pi);
+    erfc((v as f64 - 2.0 * n * q).abs() / (2.0 * (2.0 * n).sqrt() * q))
+}
+
+/// Test for the longest run of ones in a block. The block size and
+/// categories depend on the length, which must be at least 128 bits.
+pub fn longest_run<I: IntoIterator<Item = u8>>(bytes: I) -> f64 {
+    longest_run_bits(&to_bits(bytes))
+}
+
+fn longest_run_bits(bits: &[u8]) -> f64 {
+    let n = bits.len();
+    assert!(n >= 128, "the longest run test needs at least 128 bits");
+    // (block size, smallest category, category probabilities)
+    let (m, low, probs): (usize, usize, &[f64]) = if n < 6272 {
+        (8, 1, &[0.2148, 0.3672, 0.2305, 0.2148])
+    } else if n < 750000 {
+        (128, 4, &[0.1174, 0.2430, 0.2493, 0.1752, 0.1027, 0.1124])
+    } else {
+        (10000, 10, &[0.0882, 0.2092, 0.2483, 0.1933, 0.1208, 0.0675, 0.0727])
+    };
+
+    let k = probs.len() - 1;
+    let mut counts = vec![0; k + 1];
+    for block in bits.chunks(m).filter(|c| c.len() == m) {
+        let (mut run, mut longest) = (0, 0_usize);
+        for &b in block {
+            run = if b == 1 { run + 1 } else { 0 };
+            if run > longest {
+                longest = run;
+            }
+        }
+        let cat = longest.saturating_sub(low);
+        counts[if cat > k { k } else { cat }] += 1;
+    }
+
+    let blocks = (n / m) as f64;
+    let chi2 = counts.iter()
+        .zip(probs)
+        .map(|(&v, &p)| (v as f64 - blocks * p).powi(2) / (blocks * p))
+        .sum::<f64>();
+    igamc(k as f64 / 2.0, chi2 / 2.0)
+}
+
+/// Counts of each overlapping `m` bit pattern, wrapping around at the end.
+fn pattern_counts(bits: &[u8], m: usize) -> Vec<usize> {
+    let mut counts = vec![0; 1 << m];
+    if m == 0 {
+        return counts;
+    }
+    let mask = (1 << m) - 1;
+    let mut x = 0;
+    for (i, &b) in bits.iter().chain(&bits[..m - 1]).enumerate() {
+        x = ((x << 1) | b as usize) & mask;
+        if i + 1 >= m {
+            counts[x] += 1;
+        }
+    }
+    counts
+}
+
+/// Serial test: are all `m` bit patterns equally common? Returns both of
+/// SP 800-22's p-values, from the first and second differences of the
+/// pattern statistics. Needs `m >= 2`.
+pub fn serial<I: IntoIterator<Item = u8>>(bytes: I, m: usize) -> (f64, f64) {
+    serial_bits(&to_bits(bytes), m)
+}
+
+fn serial_bits(bits: &[u8], m: usize) -> (f64, f64) {
+    assert!(m >= 2, "the serial test needs m >= 2");
+    let n = bits.len() as f64;
+    let psi2 = |m: usize| -> f64 {
+        if m == 0 {
+            return 0.0;
+        }
+        let sum = pattern_counts(bits, m).iter().map(|&c| (c * c) as f64).sum::<f64>();
+        (1 << m) as f64 / n * sum - n
+    };
+    let (p0, p1, p2) = (psi2(m), psi2(m - 1), psi2(m - 2));
+    let d1 = p0 - p1;
+    let d2 = p0 - 2.0 * p1 + p2;
+    (igamc(2_f64.powi(m as i32 - 2), d1 / 2.0), igamc(2_f64.powi(m as i32 - 3), d2 / 2.0))
+}
+
+/// Approximate entropy test: compares how often overlapping `m` and `m+1`
+/// bit patterns occur against what a random sequence would give.
+pub fn approximate_entropy<I: IntoIterator<Item = u8>>(bytes: I, m: usize) -> f64 {
+    approximate_entropy_bits(&to_bits(bytes), m)
+}
+
+fn approximate_entropy_bits(bits: &[u8], m: usize) -> f64 {
+    let n = bits.len() as f64;
+    let phi = |m: usize| -> f64 {
+        pattern_counts(bits, m).iter()
+            .filter(|&&c| c > 0)
+            .map(|&c| c as f64 / n * (c as f64 / n).ln())
+            .sum()
+    };
+    let apen = phi(m) - phi(m + 1);
+    let chi2 = 2.0 * n * (LN_2 - apen);
+    igamc(2_f64.powi(m as i32 - 1), chi2 / 2.0)
+}
+
+/// Cumulative sums test: does the random walk of +1/-1 steps stray too far
+/// from zero? Returns the p-values for walking forward and backward.
+pub fn cumulative_sums<I: IntoIterator<Item = u8>>(bytes: I) -> (f64, f64) {
+    cumulative_sums_bits(&to_bits(bytes))
+}
+
+fn cumulative_sums_bits(bits: &[u8]) -> (f64, f64) {
+    (cumulative_sums_p(bits.iter()), cumulative_sums_p(bits.iter().rev()))
+}
+
+fn cumulative_sums_p<'a, I: Iterator<Item = &'a u8>>(bits: I) -> f64 {
+    let (mut n, mut s, mut z) = (0, 0_i64, 0);
+    for &b in bits {
+        s += 2 * b as i64 - 1;
+        if s.abs() > z {
+            z = s.abs();
+        }
+        n += 1;
+    }
+
+    let (n, z) = (n as f64, z as f64);
+    let sqrt_n = n.sqrt();
+    // the sums run over k between the bounds truncated toward zero, as the
+    // reference implementation does; flooring them changes short sequences
+    let mut p = 1.0;
+    let mut k = ((-n / z + 1.0) / 4.0).trunc();
+    while k <= ((n / z - 1.0) / 4.0).trunc() {
+        p -= normal_cdf((4.0 * k + 1.0) * z / sqrt_n) - normal_cdf((4.0 * k - 1.0) * z / sqrt_n);
+        k += 1.0;
+    }
+    let mut k = ((-n / z - 3.0) / 4.0).trunc();
+    while k <= ((n / z - 1.0) / 4.0).trunc() {
+        p += normal_cdf((4.0 * k + 3.0) * z / sqrt_n) - normal_cdf((4.0 * k + 1.0) * z / sqrt_n);
+        k += 1.0;
+    }
+    p
+}
+
+/// `ln(Gamma(x))` for `x > 0`, by the Lanczos approximation.
+fn ln_gamma(x: f64) -> f64 {
+    const G: f64 = 7.0;
+    const COEFFS: [f64; 9] = [0.9999999999998099,
+                              676.5203681218851,
+                              -1259.1392167224028,
+                              771.3234287776531,
+                              -176.61503916999185,
+                              12.507343278686905,
+                              -0.13857109526572012,
+                              9.984369578019572e-6,
+                              1.5056327351493116e-7];
+    let x = x - 1.0;
+    let mut a = COEFFS[0];
+    for (i, &c) in COEFFS.iter().enumerate().skip(1) {
+        a += c / (x + i as f64);
+    }
+    let t = x + G + 0.5;
+    0.5 * (2.0 * ::std::f64::consts::PI).ln() + (x + 0.5) * t.ln() - t + a.ln()
+}
+
+/// The regularized upper incomplete gamma function `Q(a, x)`, by its series
+/// for small `x` and its continued fraction otherwise.
+fn igamc(a: f64, x: f64) -> f64 {
+    const EPS: f64 = 1e-15;
+    const TINY: f64 = 1e-300;
+    if x <= 0.0 {
+        return 1.0;
+    }
+    let front = (-x + a * x.ln() - ln_gamma(a)).exp();
+
+    if x < a + 1.0 {
+        let (mut ap, mut term) = (a, 1.0 / a);
+        let mut sum = term;
+        for _ in 0..1000 {
+            ap += 1.0;
+            term *= x / ap;
+            sum += term;
+            if term.abs() < sum.abs() * EPS {
+                break;
+            }
+        }
+        return 1.0 - sum * front;
+    }
+
+    // modified Lentz
+    let mut b = x + 1.0 - a;
+    let mut c = 1.0 / TINY;
+    let mut d = 1.0 / b;
+    let mut h = d;
+    for i in 1..1000 {
+        let an = -(i as f64) * (i as f64 - a);
+        b += 2.0;
+        d = an * d + b;
+        if d.abs() < TINY {
+            d = TINY;
+        }
+        c = b + an / c;
+        if c.abs() < TINY {
+            c = TINY;
+        }
+        d = 1.0 / d;
+        let delta = d * c;
+        h *= delta;
+        if (delta - 1.0).abs() < EPS {
+            break;
+        }
+    }
+    front * h
+}
+
+fn erfc(x: f64) -> f64 {
+    if x >= 0.0 {
+        igamc(0.5, x * x)
+    } else {
+        2.0 - igamc(0.5, x * x)
+    }
+}
+
+fn normal_cdf(z: f64) -> f64 {
+    0.5 * erfc(-z / SQRT_2)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::File;
+    use std::io::Read;
+
+    use encoding::FromBase64;
+
+    use lcg::Lcg;
+    use util::{MT19937Rng, prng_crypt};
+    use super::{ALPHA, approximate_entropy, approximate_entropy_bits, block_frequency,
+                block_frequency_bits, cumulative_sums, cumulative_sums_bits, longest_run,
+                monobit, monobit_p, runs, runs_bits, serial, serial_bits};
+
+    // the worked examples in SP 800-22 section 2, mostly on the first 100
+    // bits of the binary expansion of pi (test_e below uses e)
+    const PI100: &'static str = "11001001000011111101101010100010001000010110100011\
+                                00001000110100110001001100011001100010100010111000";
+
+    fn bits(s: &str) -> Vec<u8> {
+        s.bytes().map(|c| c - b'0').collect()
+    }
+
+    fn assert_p(expected: f64, p: f64) {
+        assert!((expected - p).abs() < 1e-6, "expected p = {}, got {}", expected, p);
+    }
+
+    #[test]
+    fn test_examples() {
+        let pi = bits(PI100);
+        assert_p(0.527089, monobit_p(6, 10));
+        assert_p(0.109599, monobit_p(pi.iter().filter(|&&b| b == 1).count(), 100));
+        assert_p(0.801252, block_frequency_bits(&bits("0110011010"), 3));
+        assert_p(0.706438, block_frequency_bits(&pi, 10));
+        assert_p(0.147232, runs_bits(&bits("1001101011")));
+        assert_p(0.500798, runs_bits(&pi));
+        let (p1, p2) = serial_bits(&bits("0011011101"), 3);
+        assert_p(0.808792, p1);
+        assert_p(0.670320, p2);
+        assert_p(0.261961, approximate_entropy_bits(&bits("0100110101"), 3));
+        assert_p(0.235301, approximate_entropy_bits(&pi, 2));
+        assert_p(0.411658, cumulative_sums_bits(&bits("1011010111")).0);
+        let (forward, backward) = cumulative_sums_bits(&pi);
+        assert_p(0.219194, forward);
+        assert_p(0.114866, backward);
+    }
+
+    #[test]
+    fn test_e() {
+        // appendix B: the first million bits of e
+        let mut s = String::new();
+        File::open("./data/sts/e.txt").unwrap().read_to_string(&mut s).unwrap();
+        let e = s.from_base64().unwrap();
+        assert_eq!(125000, e.len());
+
+        assert_p(0.953749, monobit(e.iter().cloned()));
+        assert_p(0.211072, block_frequency(e.iter().cloned(), 128));
+        assert_p(0.561917, runs(e.iter().cloned()));
+        assert_p(0.718945, longest_run(e.iter().cloned()));
+        let (p1, p2) = serial(e.iter().cloned(), 2);
+        assert_p(0.843764, p1);
+        assert_p(0.561915, p2);
+        assert_p(0.700073, approximate_entropy(e.iter().cloned(), 10));
+        let (forward, backward) = cumulative_sums(e.iter().cloned());
+        assert_p(0.669887, forward);
+        assert_p(0.724266, backward);
+    }
+
+    fn battery(bytes: &[u8]) -> Vec<f64> {
+        let it = || bytes.iter().cloned();
+        let (s1, s2) = serial(it(), 4);
+        let (c1, c2) = cumulative_sums(it());
+        vec![monobit(it()),
+             block_frequency(it(), 128),
+             runs(it()),
+             longest_run(it()),
+             s1,
+             s2,
+             approximate_entropy(it(), 4),
+             c1,
+             c2]
+    }
+
+    #[test]
+    fn test_generators() {
+        let mut mt = MT19937Rng::new_unseeded();
+        let keystream = prng_crypt(&mut mt, &[0; 1 << 15]);
+        let ps = battery(&keystream);
+        assert!(ps.iter().all(|&p| p >= ALPHA), "{:?}", ps);
+
+        // the low byte of a power of two LCG repeats every 256 outputs
+        let mut lcg = Lcg::new(1103515245, 12345, 1 << 32, 1);
+        let low = (0..1 << 15).map(|_| lcg.next() as u8).collect::<Vec<_>>();
+        let ps = battery(&low);
+        assert!(ps.iter().any(|&p| p < ALPHA), "{:?}", ps);
+    }
+}
@@ -0,0 +1,262 @@
+use std::cmp;
+use std::num::Wrapping as w;
+use std::ops::Range;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, mpsc};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use util::temper;
+
+/// How far a search has got, as passed to the progress callback.
+#[derive(Clone, Debug)]
+pub struct Progress {
+    pub searched: u64,
+    pub total: u64,
+    pub found: usize,
+    pub elapsed: Duration,
+}
+
+impl Progress {
+    /// Seeds tried per second so far.
+    pub fn rate(&self) -> f64 {
+        let secs = self.elapsed.as_secs() as f64 + self.elapsed.subsec_nanos() as f64 * 1e-9;
+        if secs == 0.0 { 0.0 } else { self.searched as f64 / secs }
+    }
+
+    /// Estimated time left to search the whole range.
+    pub fn remaining(&self) -> Option<Duration> {
+        let rate = self.rate();
+        if rate == 0.0 {
+            return None;
+        }
+        let secs = (self.total - self.searched) as f64 / rate;
+        Some(Duration::new(secs as u64, ((secs % 1.0) * 1e9) as u32))
+    }
+}
+
+/// Brute force the seed of a PRNG: construct a generator for every seed in a
+/// range and test its outputs, spread over `threads` threads.
+///
+/// Threads take `chunk_size` seeds at a time from a shared counter and stop
+/// early once a match is found (for `find`) or the progress callback asks
+/// them to. The callback runs on the calling thread, at most once every
+/// `report_every` and once at the end.
+pub struct SeedSearch {
+    pub threads: usize,
+    pub chunk_size: u64,
+    pub report_every: Duration,
+}
+
+impl SeedSearch {
+    pub fn new(threads: usize) -> SeedSearch {
+        assert!(threads > 0);
+        SeedSearch {
+            threads: threads,
+            chunk_size: 1 << 16,
+            report_every: Duration::from_secs(1),
+        }
+    }
+
+    /// Some seed whose generator `matches`, if any. Not necessarily the
+    /// smallest when several do.
+    pub fn find<R, C, P>(&self, seeds: Range<u64>, make: C, matches: P) -> Option<u64>
+        where C: Fn(u64) -> R + Send + Sync + 'static,
+              P: Fn(&mut R) -> bool + Send + Sync + 'static
+    {
+        self.run(seeds, make, matches, true, |_| true).into_iter().next()
+    }
+
+    /// Every seed whose generator `matches`, in increasing order.
+    pub fn find_all<R, C, P>(&self, seeds: Range<u64>, make: C, matches: P) -> Vec<u64>
+        where C: Fn(u64) -> R + Send + Sync + 'static,
+              P: Fn(&mut R) -> bool + Send + Sync + 'static
+    {
+        self.run(seeds, make, matches, false, |_| true)
+    }
+
+    /// The general search. `progress` returns false to cancel; the matches
+    /// found up to then are still returned, sorted.
+    pub fn run<R, C, P, F>(&self, seeds: Range<u64>, make: C, matches: P,
+                           stop_at_first: bool, mut progress: F) -> Vec<u64>
+        where C: Fn(u64) -> R + Send + Sync + 'static,
+              P: Fn(&mut R) -> bool + Send + Sync + 'static,
+              F: FnMut(&Progress) -> bool
+    {
+        let start = seeds.start;
+        let end = cmp::max(seeds.start, seeds.end);
+        let total = end - start;
+        let chunk_size = self.chunk_size;
+        let chunks = ((total + chunk_size - 1) / chunk_size) as usize;
+
+        let job = Arc::new((make, matches));
+        let next_chunk = Arc::new(AtomicUsize::new(0));
+        let stop = Arc::new(AtomicBool::new(false));
+        let (tx, rx) = mpsc::channel();
+
+        let workers = (0..self.threads).map(|_| {
+            let job = job.clone();
+            let next_chunk = next_chunk.clone();
+            let stop = stop.clone();
+            let tx = tx.clone();
+
+            thread::spawn(move || {
+                while !stop.load(Ordering::Relaxed) {
+                    let c = next_chunk.fetch_add(1, Ordering::Relaxed);
+                    if c >= chunks {
+                        break;
+                    }
+                    let lo = start + c as u64 * chunk_size;
+                    let hi = cmp::min(lo + chunk_size, end);
+
+                    let mut hits = Vec::new();
+                    let mut searched = 0;
+                    for seed in lo..hi {
+                        let mut rng = (job.0)(seed);
+                        searched += 1;
+                        if (job.1)(&mut rng) {
+                            hits.push(seed);
+                            if stop_at_first {
+                                break;
+                            }
+                        }
+                    }
+                    if tx.send((searched, hits)).is_err() {
+                        break;
+                    }
+                }
+            })
+        }).collect::<Vec<_>>();
+        drop(tx);
+
+        let started = Instant::now();
+        let mut last_report = started;
+        let mut searched = 0;
+        let mut found = Vec::new();
+
+        // ends once every worker has quit and dropped its sender
+        for (n, hits) in rx.iter() {
+            searched += n;
+            found.extend(hits);
+            if stop_at_first && !found.is_empty() {
+                stop.store(true, Ordering::Relaxed);
+            }
+            if last_report.elapsed() >= self.report_every {
+                last_report = Instant::now();
+                let p = Progress {
+                    searched: searched,
+                    total: total,
+                    found: found.len(),
+                    elapsed: started.elapsed(),
+                };
+                if !progress(&p) {
+                    stop.store(true, Ordering::Relaxed);
+                }
+            }
+        }
+
+        for worker in workers {
+            worker.join().unwrap();
+        }
+        progress(&Progress {
+            searched: searched,
+            total: total,
+            found: found.len(),
+            elapsed: started.elapsed(),
+        });
+
+        found.sort();
+        found
+    }
+}
+
+/// The first output of `MT19937Rng::from_seed(seed)`, without building the
+/// whole state: it only depends on words 0, 1 and 397 of the seeded state,
+/// so this is several times faster for searching 32-bit seed spaces.
+pub fn mt19937_first_output(seed: u32) -> u32 {
+    let f = w(0x6C078965_u32);
+    let mut x = w(seed);
+    let (x0, mut x1) = (x, w(0));
+    for j in 1..398 {
+        x = f * (x ^ (x >> 30)) + w(j as u32);
+        if j == 1 {
+            x1 = x;
+        }
+    }
+    let y = (x0.0 & 0x80000000) | (x1.0 & 0x7fffffff);
+    let mag = if y & 1 == 1 { 0x9908B0DF } else { 0 };
+    temper(x.0 ^ (y >> 1) ^ mag)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use rand::{Rng, SeedableRng};
+
+    use util::MT19937Rng;
+    use super::{SeedSearch, mt19937_first_output};
+
+    #[test]
+    fn test_mt19937_first_output() {
+        for &seed in [0_u32, 1, 5489, 0x12345678, 0xffffffff].iter() {
+            let mut rng = MT19937Rng::from_seed(seed);
+            assert_eq!(rng.next_u32(), mt19937_first_output(seed));
+        }
+    }
+
+    #[test]
+    fn test_find() {
+        let mut search = SeedSearch::new(4);
+        search.chunk_size = 1000;
+
+        let out = MT19937Rng::from_seed(45678_u32).next_u32();
+        let seed = search.find(0..1 << 16, |s| MT19937Rng::from_seed(s as u32),
+                               move |rng| rng.next_u32() == out);
+        assert_eq!(Some(45678), seed);
+
+        assert_eq!(None, search.find(0..1000, |s| s, |&mut s| s == 1000));
+        assert_eq!(None, search.find(5..5, |s| s, |_| true));
+    }
+
+    #[test]
+    fn test_find_all_and_progress() {
+        let mut search = SeedSearch::new(3);
+        search.chunk_size = 100;
+        search.report_every = Duration::from_secs(0);
+
+        let seeds = search.find_all(0..10000, |s| s, |&mut s| s % 1234 == 0);
+        assert_eq!(vec![0, 1234, 2468, 3702, 4936, 6170, 7404, 8638, 9872], seeds);
+
+        // cancel from the progress callback: every thread stops after the
+        // chunk it is on
+        let tried = Arc::new(AtomicUsize::new(0));
+        let counter = tried.clone();
+        let mut reports = 0;
+        let found = search.run(0..1 << 32, |s| s, move |_| {
+            counter.fetch_add(1, Ordering::Relaxed);
+            false
+        }, false, |p| {
+            reports += 1;
+            assert_eq!(1 << 32, p.total);
+            p.searched < 2000
+        });
+        assert!(found.is_empty());
+        assert!(reports >= 2);
+        assert!(tried.load(Ordering::Relaxed) < 1 << 30);
+    }
+
+    #[test] #[ignore] // run with --release --nocapture
+    fn test_throughput() {
+        let search = SeedSearch::new(4);
+        let out = mt19937_first_output(0xffffff);
+        let seed = search.run(0..1 << 24, |s| s as u32, move |&mut s| mt19937_first_output(s) == out,
+                              false, |p| {
+            println!("{}/{} seeds, {:.0}/s, {:?} left", p.searched, p.total, p.rate(), p.remaining());
+            true
+        });
+        assert_eq!(vec![0xffffff], seed);
+    }
+}
@@ -0,0 +1,558 @@
+use std::cmp;
+use std::io::{self, Read, Write};
+use std::ops::Range;
+use std::sync::Arc;
+use std::thread;
+
+use rand::{Rng, SeedableRng};
+
+use seedsearch::mt19937_first_output;
+use util::{MT19937Rng, read_u32_le, read_u64_le, write_u32_le, write_u64_le};
+
+const TABLE_MAGIC: &'static [u8; 8] = b"MTSEEDT2";
+const RAINBOW_MAGIC: &'static [u8; 8] = b"MTRAINB1";
+
+// seeds per pass while building a `SeedTable`
+const CHUNK: u64 = 1 << 24;
+
+/// Look up the 32-bit seeds that make `MT19937Rng::from_seed` start with a
+/// given output.
+pub trait SeedLookup {
+    /// Seeds in the table whose first output is `output`, in increasing
+    /// order.
+    fn candidates(&self, output: u32) -> Vec<u32>;
+
+    /// The seed that produced `outputs` (the first outputs after seeding),
+    /// checking every candidate against the rest of them, and a generator
+    /// positioned just after them.
+    fn crack(&self, outputs: &[u32]) -> Option<(u32, MT19937Rng)> {
+        assert!(!outputs.is_empty());
+        for seed in self.candidates(outputs[0]) {
+            let mut rng = MT19937Rng::from_seed(seed);
+            if outputs.iter().all(|&y| rng.next_u32() == y) {
+                return Some((seed, rng));
+            }
+        }
+        None
+    }
+}
+
+// f(seed) for every seed in `seeds`, in order, spread over `threads` threads
+fn par_map<T, F>(seeds: Range<u64>, threads: usize, f: F) -> Vec<T>
+    where T: Send + 'static,
+          F: Fn(u64) -> T + Send + Sync + 'static
+{
+    let f = Arc::new(f);
+    let total = seeds.end - seeds.start;
+    let per_thread = (total + threads as u64 - 1) / threads as u64;
+
+    let workers = (0..threads as u64).map(|t| {
+        let f = f.clone();
+        let lo = cmp::min(seeds.start + t * per_thread, seeds.end);
+        let hi = cmp::min(lo + per_thread, seeds.end);
+        thread::spawn(move || (lo..hi).map(|s| f(s)).collect::<Vec<T>>())
+    }).collect::<Vec<_>>();
+
+    let mut out = Vec::with_capacity(total as usize);
+    for worker in workers {
+        out.extend(worker.join().unwrap());
+    }
+    out
+}
+
+// f(first outputs) for each run of up to `CHUNK` seeds in `seeds`, in
+// order, with the run's first seed
+fn for_each_chunk<F>(seeds: &Range<u64>, threads: usize, mut f: F)
+    where F: FnMut(u64, &[u32])
+{
+    let mut lo = seeds.start;
+    while lo < seeds.end {
+        let hi = cmp::min(lo + CHUNK, seeds.end);
+        let outputs = par_map(lo..hi, threads, |s| mt19937_first_output(s as u32));
+        f(lo, &outputs);
+        lo = hi;
+    }
+}
+
+// first outputs of `seeds`, spread over `threads` threads
+fn first_outputs(seeds: &[u32], threads: usize) -> Vec<u32> {
+    let per_thread = cmp::max(1 << 12, (seeds.len() + threads - 1) / threads);
+    let workers = seeds.chunks(per_thread).map(|c| {
+        let c = c.to_vec();
+        thread::spawn(move || c.into_iter().map(mt19937_first_output).collect::<Vec<_>>())
+    }).collect::<Vec<_>>();
+
+    let mut out = Vec::with_capacity(seeds.len());
+    for worker in workers {
+        out.extend(worker.join().unwrap());
+    }
+    out
+}
+
+fn check_range(seeds: &Range<u64>) {
+    assert!(seeds.start < seeds.end && seeds.end <= 1 << 32, "seeds must be a non-empty range of u32s");
+}
+
+// whether a table header's `start..start + count` is a non-empty range of u32s
+fn in_seed_range(start: u64, count: u64) -> bool {
+    match start.checked_add(count) {
+        Some(end) => count > 0 && end <= 1 << 32,
+        None => false,
+    }
+}
+
+fn bad_data(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.to_string())
+}
+
+/// Every seed in a range, sorted by first output.
+///
+/// Only the seeds are stored, 4 bytes each (16GB for all 2^32 seeds), plus
+/// an index of where each `2^bucket_bits` prefix of the output starts.
+/// Lookups binary search within a bucket, recomputing first outputs as they
+/// go, so they take a few dozen MT initializations.
+pub struct SeedTable {
+    start: u64,
+    bucket_bits: u32,
+    // bucket b holds seeds[offsets[b]..offsets[b + 1]]
+    offsets: Vec<u64>,
+    seeds: Vec<u32>,
+}
+
+impl SeedTable {
+    /// Tabulate `seeds` a bucket at a time: count the seeds in each bucket,
+    /// drop every seed into its bucket, then sort the buckets one by one.
+    /// Past the table itself that only needs room for a chunk of outputs and
+    /// the largest bucket, so all 2^32 seeds build in a little over 16GB, at
+    /// the cost of computing each first output three times. Use enough
+    /// `bucket_bits` (16, say) to keep buckets small.
+    pub fn build(seeds: Range<u64>, bucket_bits: u32, threads: usize) -> SeedTable {
+        check_range(&seeds);
+        assert!(bucket_bits <= 28);
+        let start = seeds.start;
+        let bucket = |y| SeedTable::bucket(bucket_bits, y);
+
+        let mut offsets = vec![0_u64; (1 << bucket_bits) + 1];
+        for_each_chunk(&seeds, threads, |_, outputs| {
+            for &y in outputs {
+                offsets[bucket(y) + 1] += 1;
+            }
+        });
+        for b in 0..1 << bucket_bits {
+            offsets[b + 1] += offsets[b];
+        }
+
+        let mut table = vec![0_u32; (seeds.end - seeds.start) as usize];
+        let mut next = offsets.clone();
+        for_each_chunk(&seeds, threads, |lo, outputs| {
+            for (i, &y) in outputs.iter().enumerate() {
+                let b = bucket(y);
+                table[next[b] as usize] = (lo + i as u64) as u32;
+                next[b] += 1;
+            }
+        });
+
+        for b in 0..1 << bucket_bits {
+            let bucket = &mut table[offsets[b] as usize..offsets[b + 1] as usize];
+            let mut pairs = first_outputs(bucket, threads).into_iter()
+                .zip(bucket.iter().cloned())
+                .collect::<Vec<_>>();
+            pairs.sort();
+            for (s, (_, seed)) in bucket.iter_mut().zip(pairs) {
+                *s = seed;
+            }
+        }
+
+        SeedTable {
+            start: start,
+            bucket_bits: bucket_bits,
+            offsets: offsets,
+            seeds: table,
+        }
+    }
+
+    fn bucket(bucket_bits: u32, output: u32) -> usize {
+        if bucket_bits == 0 { 0 } else { (output >> (32 - bucket_bits)) as usize }
+    }
+
+    pub fn seeds(&self) -> Range<u64> {
+        self.start..self.start + self.seeds.len() as u64
+    }
+
+    /// `[magic][start u64][count u64][bucket_bits u32]`, then the size of
+    /// each bucket as a u64 and the seeds as u32s, all little endian.
+    pub fn write_to<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        let mut buf4 = [0_u8; 4];
+        let mut buf8 = [0_u8; 8];
+
+        try!(w.write_all(TABLE_MAGIC));
+        for &x in [self.start, self.seeds.len() as u64].iter() {
+            write_u64_le(&mut buf8, x);
+            try!(w.write_all(&buf8));
+        }
+        write_u32_le(&mut buf4, self.bucket_bits);
+        try!(w.write_all(&buf4));
+        for b in 0..1 << self.bucket_bits {
+            write_u64_le(&mut buf8, self.offsets[b + 1] - self.offsets[b]);
+            try!(w.write_all(&buf8));
+        }
+        for &s in &self.seeds {
+            write_u32_le(&mut buf4, s);
+            try!(w.write_all(&buf4));
+        }
+        Ok(())
+    }
+
+    pub fn read_from<R: Read>(r: &mut R) -> io::Result<SeedTable> {
+        let mut buf4 = [0_u8; 4];
+        let mut buf8 = [0_u8; 8];
+
+        try!(r.read_exact(&mut buf8));
+        if &buf8 != TABLE_MAGIC {
+            return Err(bad_data("not a seed table"));
+        }
+        try!(r.read_exact(&mut buf8));
+        let start = read_u64_le(&buf8);
+        try!(r.read_exact(&mut buf8));
+        let count = read_u64_le(&buf8);
+        try!(r.read_exact(&mut buf4));
+        let bucket_bits = read_u32_le(&buf4);
+        if !in_seed_range(start, count) || bucket_bits > 28 {
+            return Err(bad_data("bad seed table header"));
+        }
+
+        let mut offsets = vec![0_u64; (1 << bucket_bits) + 1];
+        for b in 0..1 << bucket_bits {
+            try!(r.read_exact(&mut buf8));
+            offsets[b + 1] = match offsets[b].checked_add(read_u64_le(&buf8)) {
+                Some(end) if end <= count => end,
+                _ => return Err(bad_data("seed table buckets don't add up")),
+            };
+        }
+        if offsets[1 << bucket_bits] != count {
+            return Err(bad_data("seed table buckets don't add up"));
+        }
+
+        // grown as the seeds arrive rather than trusting `count` up front
+        let mut seeds = Vec::new();
+        for _ in 0..count {
+            try!(r.read_exact(&mut buf4));
+            seeds.push(read_u32_le(&buf4));
+        }
+
+        Ok(SeedTable {
+            start: start,
+            bucket_bits: bucket_bits,
+            offsets: offsets,
+            seeds: seeds,
+        })
+    }
+}
+
+impl SeedLookup for SeedTable {
+    fn candidates(&self, output: u32) -> Vec<u32> {
+        let b = SeedTable::bucket(self.bucket_bits, output);
+        let bucket = &self.seeds[self.offsets[b] as usize..self.offsets[b + 1] as usize];
+
+        // first seed in the bucket whose output isn't below `output`
+        let (mut lo, mut hi) = (0, bucket.len());
+        while lo < hi {
+            let mid = (lo + hi) / 2;
+            if mt19937_first_output(bucket[mid]) < output {
+                lo = mid + 1;
+            } else {
+                hi = mid;
+            }
+        }
+
+        let mut out = bucket[lo..].iter()
+            .cloned()
+            .take_while(|&s| mt19937_first_output(s) == output)
+            .collect::<Vec<_>>();
+        out.sort();
+        out
+    }
+}
+
+/// A Hellman time-memory trade-off with rainbow reductions: chains of
+/// `seed -> reduce_i(first_output(seed))`, of which only the two ends are
+/// stored, 8 bytes per chain.
+///
+/// A table of `m` chains of length `t` covers up to `m * t` seeds, less the
+/// chains that merge, and a lookup costs about `t^2 / 2` MT initializations.
+/// Unlike `SeedTable` it can miss seeds; build a few tables with different
+/// `salt`s, or more chains, to cover more of the range.
+pub struct RainbowTable {
+    start: u64,
+    count: u64,
+    chain_len: u32,
+    salt: u32,
+    // (end, start) of each chain, sorted by end
+    chains: Vec<(u32, u32)>,
+}
+
+impl RainbowTable {
+    /// Build `chains` chains of `chain_len` steps, starting from seeds spread
+    /// evenly over `seeds`. Chains that end in the same place are merged
+    /// copies of each other beyond some point, so only one of them is kept.
+    pub fn build(seeds: Range<u64>, chains: u64, chain_len: u32, salt: u32, threads: usize)
+        -> RainbowTable
+    {
+        check_range(&seeds);
+        assert!(chains > 0 && chain_len > 0);
+        let (start, count) = (seeds.start, seeds.end - seeds.start);
+        let stride = cmp::max(1, count / chains);
+
+        let mut ends = par_map(0..cmp::min(chains, count), threads, move |j| {
+            let s = (start + j * stride) as u32;
+            let mut x = s;
+            for i in 0..chain_len {
+                x = RainbowTable::reduce(start, count, salt, i, mt19937_first_output(x));
+            }
+            (x, s)
+        });
+        ends.sort();
+        ends.dedup_by_key(|c| c.0);
+
+        RainbowTable {
+            start: start,
+            count: count,
+            chain_len: chain_len,
+            salt: salt,
+            chains: ends,
+        }
+    }
+
+    // map an output back into the seed range, differently for each column
+    fn reduce(start: u64, count: u64, salt: u32, i: u32, y: u32) -> u32 {
+        let mixed = y ^ i.wrapping_add(salt).wrapping_mul(0x9E3779B9);
+        (start + mixed as u64 % count) as u32
+    }
+
+    fn step(&self, i: u32, x: u32) -> u32 {
+        RainbowTable::reduce(self.start, self.count, self.salt, i, mt19937_first_output(x))
+    }
+
+    pub fn chains(&self) -> usize {
+        self.chains.len()
+    }
+
+    /// `[magic][start u64][count u64][chain_len u32][salt u32][chains u64]`,
+    /// then each chain's end and start as u32s, all little endian.
+    pub fn write_to<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        let mut buf4 = [0_u8; 4];
+        let mut buf8 = [0_u8; 8];
+
+        try!(w.write_all(RAINBOW_MAGIC));
+        for &x in [self.start, self.count].iter() {
+            write_u64_le(&mut buf8, x);
+            try!(w.write_all(&buf8));
+        }
+        for &x in [self.chain_len, self.salt].iter() {
+            write_u32_le(&mut buf4, x);
+            try!(w.write_all(&buf4));
+        }
+        write_u64_le(&mut buf8, self.chains.len() as u64);
+        try!(w.write_all(&buf8));
+        for &(end, start) in &self.chains {
+            write_u32_le(&mut buf4, end);
+            try!(w.write_all(&buf4));
+            write_u32_le(&mut buf4, start);
+            try!(w.write_all(&buf4));
+        }
+        Ok(())
+    }
+
+    pub fn read_from<R: Read>(r: &mut R) -> io::Result<RainbowTable> {
+        let mut buf4 = [0_u8; 4];
+        let mut buf8 = [0_u8; 8];
+
+        try!(r.read_exact(&mut buf8));
+        if &buf8 != RAINBOW_MAGIC {
+            return Err(bad_data("not a rainbow table"));
+        }
+        let mut header = [0_u64; 2];
+        for x in header.iter_mut() {
+            try!(r.read_exact(&mut buf8));
+            *x = read_u64_le(&buf8);
+        }
+        let (start, count) = (header[0], header[1]);
+        try!(r.read_exact(&mut buf4));
+        let chain_len = read_u32_le(&buf4);
+        try!(r.read_exact(&mut buf4));
+        let salt = read_u32_le(&buf4);
+        try!(r.read_exact(&mut buf8));
+        let n = read_u64_le(&buf8);
+        // every chain ends on a distinct seed in the range
+        if !in_seed_range(start, count) || chain_len == 0 || n > count {
+            return Err(bad_data("bad rainbow table header"));
+        }
+
+        let mut chains = Vec::new();
+        for _ in 0..n {
+            try!(r.read_exact(&mut buf4));
+            let end = read_u32_le(&buf4);
+            try!(r.read_exact(&mut buf4));
+            chains.push((end, read_u32_le(&buf4)));
+        }
+        if chains.windows(2).any(|c| c[0].0 >= c[1].0) {
+            return Err(bad_data("rainbow table chains out of order"));
+        }
+
+        Ok(RainbowTable {
+            start: start,
+            count: count,
+            chain_len: chain_len,
+            salt: salt,
+            chains: chains,
+        })
+    }
+}
+
+impl SeedLookup for RainbowTable {
+    fn candidates(&self, output: u32) -> Vec<u32> {
+        let mut out = Vec::new();
+
+        // guess the column the seed sits in, last first since those are
+        // cheapest, walk to the end of the chain and look the end up
+        for col in (0..self.chain_len).rev() {
+            let mut x = RainbowTable::reduce(self.start, self.count, self.salt, col, output);
+            for i in col + 1..self.chain_len {
+                x = self.step(i, x);
+            }
+            let chain = match self.chains.binary_search_by(|c| c.0.cmp(&x)) {
+                Ok(idx) => self.chains[idx],
+                Err(_) => continue,
+            };
+
+            // rebuild the chain up to that column; a false alarm if the
+            // chains only merge after it
+            let mut s = chain.1;
+            for i in 0..col {
+                s = self.step(i, s);
+            }
+            if mt19937_first_output(s) == output {
+                out.push(s);
+            }
+        }
+
+        out.sort();
+        out.dedup();
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{self, Cursor};
+
+    use rand::{Rng, SeedableRng};
+
+    use seedsearch::mt19937_first_output;
+    use util::MT19937Rng;
+    use super::{RainbowTable, SeedLookup, SeedTable};
+
+    // a day's worth of timestamps before some point in 2017
+    const NOW: u64 = 1500000000;
+    const DAY: u64 = 86400;
+
+    #[test]
+    fn test_seed_table() {
+        let table = SeedTable::build(NOW - DAY..NOW, 12, 4);
+
+        let mut saved = Vec::new();
+        table.write_to(&mut saved).unwrap();
+        assert_eq!(8 + 16 + 4 + 8 * 4096 + 4 * DAY as usize, saved.len());
+        let table = SeedTable::read_from(&mut Cursor::new(saved)).unwrap();
+        assert_eq!(NOW - DAY..NOW, table.seeds());
+
+        // a reset token from an hour ago
+        let seed = (NOW - 3600) as u32;
+        let mut rng = MT19937Rng::from_seed(seed);
+        let outputs = (0..3).map(|_| rng.next_u32()).collect::<Vec<_>>();
+
+        assert!(table.candidates(outputs[0]).contains(&seed));
+        let (found, mut clone) = table.crack(&outputs).unwrap();
+        assert_eq!(seed, found);
+        assert_eq!(rng.next_u32(), clone.next_u32());
+
+        // every seed can be found
+        for s in (NOW - DAY..NOW).filter(|s| s % 997 == 0) {
+            assert!(table.candidates(mt19937_first_output(s as u32)).contains(&(s as u32)));
+        }
+
+        // but not seeds outside the table
+        let mut rng = MT19937Rng::from_seed(NOW as u32 + 10);
+        assert!(table.crack(&[rng.next_u32(), rng.next_u32()]).is_none());
+
+        assert!(SeedTable::read_from(&mut Cursor::new(b"MTRAINB1".to_vec())).is_err());
+    }
+
+    #[test]
+    fn test_rainbow_table() {
+        let seeds = NOW - (1 << 16)..NOW;
+        // 2^16 seeds, 2^11 chains of 64 steps; about half the chains merge,
+        // so the three tables take a tenth of a SeedTable's space
+        let tables = (0..3)
+            .map(|salt| RainbowTable::build(seeds.clone(), 1 << 11, 64, salt, 4))
+            .collect::<Vec<_>>();
+
+        let mut saved = Vec::new();
+        tables[0].write_to(&mut saved).unwrap();
+        assert_eq!(40 + 8 * tables[0].chains(), saved.len());
+        let t0 = RainbowTable::read_from(&mut Cursor::new(saved)).unwrap();
+        assert_eq!(tables[0].chains, t0.chains);
+
+        let mut hits = 0;
+        let mut tried = 0;
+        for s in seeds.clone().filter(|s| s % 331 == 0) {
+            let mut rng = MT19937Rng::from_seed(s as u32);
+            let outputs = [rng.next_u32(), rng.next_u32()];
+            tried += 1;
+            if let Some((found, _)) = tables.iter().filter_map(|t| t.crack(&outputs)).next() {
+                assert_eq!(s as u32, found);
+                hits += 1;
+            }
+        }
+        // each table covers about 60% of the range
+        assert!(hits * 10 > tried * 8, "{} of {}", hits, tried);
+    }
+
+    #[test]
+    fn test_read_bad_header() {
+        let le = |x: u64, n: usize| (0..n).map(|i| (x >> (8 * i)) as u8).collect::<Vec<_>>();
+
+        // start + count wrapping around u64
+        let mut bytes = b"MTSEEDT2".to_vec();
+        bytes.extend(le(!0 - 1, 8));
+        bytes.extend(le(4, 8));
+        bytes.extend(le(0, 4));
+        assert!(SeedTable::read_from(&mut Cursor::new(bytes)).is_err());
+
+        // all 2^32 seeds in one bucket, but truncated
+        let mut bytes = b"MTSEEDT2".to_vec();
+        bytes.extend(le(0, 8));
+        bytes.extend(le(1 << 32, 8));
+        bytes.extend(le(0, 4));
+        bytes.extend(le(1 << 32, 8));
+        let err = SeedTable::read_from(&mut Cursor::new(bytes)).err().unwrap();
+        assert_eq!(io::ErrorKind::UnexpectedEof, err.kind());
+
+        let mut bytes = b"MTRAINB1".to_vec();
+        bytes.extend(le(!0 - 1, 8));
+        bytes.extend(le(4, 8));
+        bytes.extend(le(64, 4));
+        bytes.extend(le(0, 4));
+        bytes.extend(le(1, 8));
+        assert!(RainbowTable::read_from(&mut Cursor::new(bytes)).is_err());
+
+        // more chains than seeds
+        let mut bytes = b"MTRAINB1".to_vec();
+        bytes.extend(le(0, 8));
+        bytes.extend(le(16, 8));
+        bytes.extend(le(64, 4));
+        bytes.extend(le(0, 4));
+        bytes.extend(le(!0, 8));
+        assert!(RainbowTable::read_from(&mut Cursor::new(bytes)).is_err());
+    }
+}
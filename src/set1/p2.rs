@@ -1,5 +1,5 @@
 use util::xor_bytes;
-use serialize::hex::{FromHex, ToHex};
+use encoding::{FromHex, ToHex};
 
 #[test]
 fn run() {
@@ -1,4 +1,4 @@
-use serialize::hex::FromHex;
+use encoding::FromHex;
 
 use freq::{dict, dict_englishness, most_english};
 use util::xor_bytes;
@@ -2,7 +2,7 @@ use std::io::prelude::*;
 use std::io::BufReader;
 use std::fs::File;
 
-use serialize::hex::FromHex;
+use encoding::FromHex;
 
 use freq::{english_freq_vec, dict, dict_englishness, relative_englishness, most_english};
 use util::xor_bytes;
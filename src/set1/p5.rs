@@ -1,4 +1,4 @@
-use serialize::hex::ToHex;
+use encoding::ToHex;
 
 use util::xor_bytes;
 
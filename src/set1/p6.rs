@@ -1,8 +1,7 @@
 use std::io::prelude::*;
-use std::io::BufReader;
 use std::fs::File;
 
-use serialize::base64::FromBase64;
+use encoding::{DecodeReader, Decoder, STANDARD};
 
 use freq::{english_freq_vec, freq_englishness, most_english, dict, dict_englishness};
 use util::{xor_bytes, bytes_hamming_dist};
@@ -10,16 +9,8 @@ use util::{xor_bytes, bytes_hamming_dist};
 #[test]
 fn run() {
     let f = File::open("./data/6.txt").unwrap();
-    let reader = BufReader::new(f);
-
-    let buf: String = reader.lines()
-        .map(|line| line.unwrap())
-        .fold(String::new(), |mut buf, line| {
-            buf.push_str(line.trim());
-            buf
-        });
-
-    let bytes = buf.as_str().from_base64().unwrap();
+    let mut bytes = Vec::new();
+    DecodeReader::new(f, Decoder::base64(STANDARD)).read_to_end(&mut bytes).unwrap();
 
     let mut norm_dists: Vec<(usize, f32)> = (2..40).map(|k| {
         let d1 = bytes_hamming_dist(&bytes[0..k], &bytes[k..2*k]);
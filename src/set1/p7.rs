@@ -3,7 +3,7 @@ use std::fs::File;
 
 use ssl::symm::{self, decrypt};
 
-use serialize::base64::FromBase64;
+use encoding::FromBase64;
 
 #[test]
 fn run() {
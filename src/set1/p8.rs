@@ -4,7 +4,7 @@ use std::fs::File;
 
 use std::collections::HashSet;
 
-use serialize::hex::FromHex;
+use encoding::FromHex;
 
 fn repeated_blocks(data: &[u8], blocksize: usize) -> u32 {
     let mut count = 0;
@@ -1,7 +1,7 @@
 use std::collections::HashSet;
 
 use rand::{Rng, weak_rng};
-use serialize::hex::ToHex;
+use encoding::ToHex;
 use ssl::symm::{self, encrypt};
 
 #[derive(PartialEq, Eq, Debug)]
@@ -2,6 +2,7 @@ use std::time::{SystemTime, UNIX_EPOCH};
 
 use rand::{Rng, SeedableRng, weak_rng};
 
+use seedsearch::SeedSearch;
 use util::MT19937Rng;
 
 fn epoch_time() -> u64 {
@@ -32,20 +33,13 @@ fn run() {
 
     // Attacker
 
-    // We simply step backward from our current time until a freshly seeded
+    // We simply search backward from our current time until a freshly seeded
     // rng produces the same out value.
-    
-    let mut recovered_seed = None;
-
-    for i in 0..2000 {
-        let seed2: u32 = ((t - i) & 0xFFFFFFFF) as u32;
-        mt_rng.reseed(seed2);
-        let out2 = mt_rng.next_u32();
-        if out == out2 {
-            recovered_seed = Some(seed2);
-        }
-    }
-
-    assert!(recovered_seed.is_some());
-    assert_eq!(seed, recovered_seed.unwrap());
+
+    let search = SeedSearch::new(4);
+    let recovered_seed = search.find(t - 2000..t + 1,
+                                     |s| MT19937Rng::from_seed(s as u32),
+                                     move |rng| rng.next_u32() == out);
+
+    assert_eq!(Some(seed as u64), recovered_seed);
 }
@@ -1,5 +1,9 @@
 use rand::{weak_rng, Rng, SeedableRng};
+use encoding::ToHex;
 
+use otp::SystemClock;
+use seedsearch::SeedSearch;
+use tokens::{TokenDetector, TokenGenerator};
 use util::{MT19937Rng, prng_crypt};
 
 #[test]
@@ -21,13 +25,22 @@ fn run() {
     let test_input = "__AAAAAAAAAAAAAA".as_bytes();
 
     // brute force seeds
-    let rec_seed = (0..(1 << 16))
-        .find(|&s: &u32| {
-            ks_rng.reseed(s);
-            let test_ctxt = prng_crypt(&mut ks_rng, &test_input);
-            &ctxt[2..] == &test_ctxt[2..]
-        });
-
-    assert!(rec_seed.is_some());
-    assert_eq!(seed, rec_seed.unwrap());
+    let search = SeedSearch::new(4);
+    let rec_seed = search.find(0..1 << 16, |s| MT19937Rng::from_seed(s as u32), move |rng| {
+        let test_ctxt = prng_crypt(rng, &test_input);
+        &ctxt[2..] == &test_ctxt[2..]
+    });
+
+    assert_eq!(Some(seed as u64), rec_seed);
+}
+
+#[test]
+fn run_token() {
+    let token = TokenGenerator::new(16, SystemClock).generate();
+    let detector = TokenDetector::new(3600, SystemClock);
+    assert!(detector.detect(&token).is_some());
+
+    let mut wk_rng = weak_rng();
+    let random_token = wk_rng.gen_iter::<u8>().take(16).collect::<Vec<u8>>().to_hex();
+    assert!(detector.detect(&random_token).is_none());
 }
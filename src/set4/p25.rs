@@ -3,7 +3,7 @@ use std::io::BufReader;
 use std::fs::File;
 
 use rand::{Rng, weak_rng};
-use serialize::base64::FromBase64;
+use encoding::FromBase64;
 use ssl::symm::{self, decrypt};
 
 use crypto::aes;
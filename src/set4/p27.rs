@@ -1,5 +1,5 @@
 use rand::{Rng, weak_rng};
-use serialize::base64::FromBase64;
+use encoding::FromBase64;
 use ssl::symm::{self, encrypt, decrypt};
 
 use util::xor_bytes;
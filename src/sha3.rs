@@ -0,0 +1,267 @@
+use std::cmp;
+
+use rust_crypto::digest::Digest;
+
+/// SHA-3 and SHAKE (FIPS 202) on top of Keccak-f[1600].
+///
+/// Unlike `Sha1` and `Md4`, a sponge never reveals its whole internal state:
+/// the digest is squeezed out of the first `rate` bytes, while the remaining
+/// `capacity` bytes stay hidden. Knowing `H(key || msg)` therefore isn't
+/// enough to resume hashing, which is what the p29/p30 length extension
+/// attacks rely on.
+#[derive(Copy)]
+pub struct Sha3 {
+    state: [u64; 25],
+    rate: usize,
+    output: usize,
+    domain: u8,
+    buf: [u8; MAX_RATE],
+    buf_len: usize,
+}
+
+const MAX_RATE: usize = 168;
+
+// domain separation bits plus the first bit of the pad10*1 padding
+const SHA3_DOMAIN: u8 = 0x06;
+const SHAKE_DOMAIN: u8 = 0x1f;
+
+const ROUND_CONSTANTS: [u64; 24] = [
+    0x0000000000000001, 0x0000000000008082, 0x800000000000808a, 0x8000000080008000,
+    0x000000000000808b, 0x0000000080000001, 0x8000000080008081, 0x8000000000008009,
+    0x000000000000008a, 0x0000000000000088, 0x0000000080008009, 0x000000008000000a,
+    0x000000008000808b, 0x800000000000008b, 0x8000000000008089, 0x8000000000008003,
+    0x8000000000008002, 0x8000000000000080, 0x000000000000800a, 0x800000008000000a,
+    0x8000000080008081, 0x8000000000008080, 0x0000000080000001, 0x8000000080008008,
+];
+
+// rotation offsets and destination lanes of the combined rho and pi steps,
+// following lane 1 around its cycle
+const RHO: [u32; 24] = [
+    1, 3, 6, 10, 15, 21, 28, 36, 45, 55, 2, 14,
+    27, 41, 56, 8, 25, 43, 62, 18, 39, 61, 20, 44,
+];
+const PI: [usize; 24] = [
+    10, 7, 11, 17, 18, 3, 5, 16, 8, 21, 24, 4,
+    15, 23, 19, 13, 12, 2, 20, 14, 22, 9, 6, 1,
+];
+
+/// The Keccak-f[1600] permutation. Lane `(x, y)` lives at `state[x + 5 * y]`.
+pub fn keccak_f(state: &mut [u64; 25]) {
+    for &rc in ROUND_CONSTANTS.iter() {
+        // theta
+        let mut c = [0_u64; 5];
+        for x in 0..5 {
+            c[x] = state[x] ^ state[x + 5] ^ state[x + 10] ^ state[x + 15] ^ state[x + 20];
+        }
+        for x in 0..5 {
+            let d = c[(x + 4) % 5] ^ c[(x + 1) % 5].rotate_left(1);
+            for y in 0..5 {
+                state[x + 5 * y] ^= d;
+            }
+        }
+
+        // rho and pi
+        let mut last = state[1];
+        for i in 0..24 {
+            let tmp = state[PI[i]];
+            state[PI[i]] = last.rotate_left(RHO[i]);
+            last = tmp;
+        }
+
+        // chi
+        for y in 0..5 {
+            let row = [state[5 * y], state[5 * y + 1], state[5 * y + 2],
+                       state[5 * y + 3], state[5 * y + 4]];
+            for x in 0..5 {
+                state[x + 5 * y] = row[x] ^ (!row[(x + 1) % 5] & row[(x + 2) % 5]);
+            }
+        }
+
+        // iota
+        state[0] ^= rc;
+    }
+}
+
+impl Sha3 {
+    fn new(rate: usize, output: usize, domain: u8) -> Sha3 {
+        assert!(rate <= MAX_RATE && rate % 8 == 0);
+        Sha3 {
+            state: [0; 25],
+            rate: rate,
+            output: output,
+            domain: domain,
+            buf: [0; MAX_RATE],
+            buf_len: 0,
+        }
+    }
+
+    pub fn sha3_224() -> Sha3 { Sha3::new(144, 28, SHA3_DOMAIN) }
+    pub fn sha3_256() -> Sha3 { Sha3::new(136, 32, SHA3_DOMAIN) }
+    pub fn sha3_384() -> Sha3 { Sha3::new(104, 48, SHA3_DOMAIN) }
+    pub fn sha3_512() -> Sha3 { Sha3::new(72, 64, SHA3_DOMAIN) }
+
+    /// SHAKE128 producing `output` bytes from `result`.
+    pub fn shake_128(output: usize) -> Sha3 { Sha3::new(168, output, SHAKE_DOMAIN) }
+
+    /// SHAKE256 producing `output` bytes from `result`.
+    pub fn shake_256(output: usize) -> Sha3 { Sha3::new(136, output, SHAKE_DOMAIN) }
+
+    /// Pad the absorbed input and squeeze `out.len()` bytes, without
+    /// disturbing the running hash. For SHAKE any length is fine; SHA-3
+    /// outputs are just a fixed-length prefix of the same stream.
+    pub fn squeeze(&self, out: &mut [u8]) {
+        let mut state = self.state;
+        let mut last = [0_u8; MAX_RATE];
+        last[..self.buf_len].copy_from_slice(&self.buf[..self.buf_len]);
+        last[self.buf_len] ^= self.domain;
+        last[self.rate - 1] ^= 0x80;
+        absorb_block(&mut state, &last[..self.rate]);
+
+        for (i, chunk) in out.chunks_mut(self.rate).enumerate() {
+            if i > 0 {
+                keccak_f(&mut state);
+            }
+            for (j, byte) in chunk.iter_mut().enumerate() {
+                *byte = (state[j / 8] >> (8 * (j % 8))) as u8;
+            }
+        }
+    }
+}
+
+fn absorb_block(state: &mut [u64; 25], block: &[u8]) {
+    for (lane, word) in state.iter_mut().zip(block.chunks(8)) {
+        let mut x = 0_u64;
+        for (j, &b) in word.iter().enumerate() {
+            x |= (b as u64) << (8 * j);
+        }
+        *lane ^= x;
+    }
+    keccak_f(state);
+}
+
+impl Clone for Sha3 {
+    fn clone(&self) -> Sha3 { *self }
+}
+
+impl Digest for Sha3 {
+    fn input(&mut self, mut data: &[u8]) {
+        let rate = self.rate;
+        if self.buf_len > 0 {
+            let amt = cmp::min(data.len(), rate - self.buf_len);
+            self.buf[self.buf_len..self.buf_len + amt].copy_from_slice(&data[..amt]);
+            self.buf_len += amt;
+            data = &data[amt..];
+            if self.buf_len < rate {
+                return
+            }
+            absorb_block(&mut self.state, &self.buf[..rate]);
+            self.buf_len = 0;
+        }
+
+        while data.len() >= rate {
+            absorb_block(&mut self.state, &data[..rate]);
+            data = &data[rate..];
+        }
+
+        self.buf[..data.len()].copy_from_slice(data);
+        self.buf_len = data.len();
+    }
+
+    fn result(&mut self, out: &mut [u8]) {
+        let n = self.output;
+        self.squeeze(&mut out[..n]);
+    }
+
+    fn reset(&mut self) {
+        self.state = [0; 25];
+        self.buf_len = 0;
+    }
+
+    fn output_bits(&self) -> usize { self.output * 8 }
+    fn output_bytes(&self) -> usize { self.output }
+    fn block_size(&self) -> usize { self.rate }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{Rng, weak_rng};
+
+    use rust_crypto::digest::Digest;
+    use rust_crypto::sha3::Sha3 as Sha3Ref;
+    use encoding::ToHex;
+
+    use super::Sha3;
+
+    fn hex_digest(mut h: Sha3, input: &[u8]) -> String {
+        let mut out = vec![0_u8; h.output_bytes()];
+        h.input(input);
+        h.result(&mut out);
+        out.to_hex()
+    }
+
+    #[test]
+    fn test_correctness() {
+        let abc = b"abc";
+        assert_eq!("e642824c3f8cf24ad09234ee7d3c766fc9a3a5168d0c94ad73b46fdf",
+                   hex_digest(Sha3::sha3_224(), abc));
+        assert_eq!("3a985da74fe225b2045c172d6bd390bd855f086e3e9d525b46bfe24511431532",
+                   hex_digest(Sha3::sha3_256(), abc));
+        assert_eq!("ec01498288516fc926459f58e2c6ad8df9b473cb0fc08c2596da7cf0e49be4b2\
+                    98d88cea927ac7f539f1edf228376d25",
+                   hex_digest(Sha3::sha3_384(), abc));
+        assert_eq!("b751850b1a57168a5693cd924b6b096e08f621827444f70d884f5d0240d2712e\
+                    10e116e9192af3c91a7ec57647e3934057340b4cf408d5a56592f8274eec53f0",
+                   hex_digest(Sha3::sha3_512(), abc));
+        assert_eq!("a7ffc6f8bf1ed76651c14756a061d662f580ff4de43b49fa82d80a4b80f8434a",
+                   hex_digest(Sha3::sha3_256(), b""));
+
+        assert_eq!("7f9c2ba4e88f827d616045507605853ed73b8093f6efbc88eb1a6eacfa66ef26",
+                   hex_digest(Sha3::shake_128(32), b""));
+        assert_eq!("46b9dd2b0ba88d13233b3feb743eeb243fcd52ea62b81b82b50c27646ed5762f",
+                   hex_digest(Sha3::shake_256(32), b""));
+    }
+
+    #[test]
+    fn test_random() {
+        let mut rng = weak_rng();
+        let mut buf = [0_u8; 1024];
+        let pairs = [
+            (Sha3::sha3_224(), Sha3Ref::sha3_224()),
+            (Sha3::sha3_256(), Sha3Ref::sha3_256()),
+            (Sha3::sha3_384(), Sha3Ref::sha3_384()),
+            (Sha3::sha3_512(), Sha3Ref::sha3_512()),
+        ];
+        for &(m, m_ref) in pairs.iter() {
+            let (mut m, mut m_ref) = (m, m_ref);
+            for _ in 0..200 {
+                let len: usize = rng.gen_range(0, 1024);
+                let split: usize = rng.gen_range(0, len + 1);
+                rng.fill_bytes(&mut buf[0..len]);
+                m.input(&buf[0..split]);
+                m.input(&buf[split..len]);
+                m_ref.input(&buf[0..len]);
+                assert_eq!(m_ref.result_str(), m.result_str());
+                m.reset();
+                m_ref.reset();
+            }
+        }
+    }
+
+    #[test]
+    fn test_shake_squeeze() {
+        // longer outputs extend shorter ones, across several permutations
+        let mut h = Sha3::shake_128(0);
+        h.input(b"squeeze me");
+        let mut long = [0_u8; 500];
+        let mut short = [0_u8; 100];
+        h.squeeze(&mut long);
+        h.squeeze(&mut short);
+        assert_eq!(&long[..100], &short[..]);
+
+        let mut m_ref = Sha3Ref::shake_128();
+        let mut expected = [0_u8; 500];
+        m_ref.input(b"squeeze me");
+        m_ref.result(&mut expected);
+        assert_eq!(&expected[..], &long[..]);
+    }
+}
@@ -0,0 +1,132 @@
+use std::cmp;
+
+use rand::SeedableRng;
+use encoding::{FromHex, ToHex};
+
+use otp::Clock;
+use seedsearch::mt19937_first_output;
+use util::{MT19937Rng, prng_crypt};
+
+/// The `len` token bytes MT19937 seeded with `seed` produces: its
+/// `prng_crypt` keystream, four little endian bytes per output.
+pub fn token_bytes(seed: u32, len: usize) -> Vec<u8> {
+    let mut rng = MT19937Rng::from_seed(seed);
+    prng_crypt(&mut rng, &vec![0_u8; len])
+}
+
+/// Password reset tokens made the weak way: seed MT19937 with the current
+/// Unix time and hex encode the first `len` bytes it produces.
+pub struct TokenGenerator<C> {
+    len: usize,
+    clock: C,
+}
+
+impl<C: Clock> TokenGenerator<C> {
+    pub fn new(len: usize, clock: C) -> TokenGenerator<C> {
+        assert!(len > 0);
+        TokenGenerator {
+            len: len,
+            clock: clock,
+        }
+    }
+
+    pub fn generate(&self) -> String {
+        token_bytes(self.clock.now() as u32, self.len).to_hex()
+    }
+}
+
+/// Decide whether a token came from a `TokenGenerator` by trying every
+/// timestamp from `window` seconds before now to `ahead` seconds after it,
+/// to allow for clock skew between us and the service.
+pub struct TokenDetector<C> {
+    pub window: u64,
+    pub ahead: u64,
+    clock: C,
+}
+
+impl<C: Clock> TokenDetector<C> {
+    pub fn new(window: u64, clock: C) -> TokenDetector<C> {
+        TokenDetector {
+            window: window,
+            ahead: 60,
+            clock: clock,
+        }
+    }
+
+    /// The seed of the generator that made `token`, or `None` if it's not
+    /// hex or no timestamp in the window explains it.
+    pub fn detect(&self, token: &str) -> Option<u32> {
+        let bytes = match token.from_hex() {
+            Ok(b) => b,
+            Err(_) => return None,
+        };
+        if bytes.is_empty() {
+            return None;
+        }
+
+        // the first output settles almost every seed without seeding a
+        // whole generator
+        let n = cmp::min(4, bytes.len());
+        let mut first = [0_u8; 4];
+        first[..n].copy_from_slice(&bytes[..n]);
+        let prefix = first.iter().rev().fold(0_u32, |acc, &b| (acc << 8) | b as u32);
+        let mask = if n == 4 { !0 } else { (1_u32 << (8 * n)) - 1 };
+
+        let now = self.clock.now();
+        let lo = now.saturating_sub(self.window);
+        (lo..now + self.ahead + 1)
+            .map(|t| t as u32)
+            .filter(|&seed| mt19937_first_output(seed) & mask == prefix)
+            .find(|&seed| token_bytes(seed, bytes.len()) == bytes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+
+    use rand::{Rng, thread_rng};
+    use encoding::ToHex;
+
+    use super::{TokenDetector, TokenGenerator, token_bytes};
+
+    const NOW: u64 = 1500000000;
+
+    #[test]
+    fn test_detect() {
+        let now = Cell::new(NOW);
+        let gen = TokenGenerator::new(16, || now.get());
+        let token = gen.generate();
+        assert_eq!(32, token.len());
+        assert_eq!(token_bytes(NOW as u32, 16).to_hex(), token);
+
+        // checked ten minutes later
+        now.set(NOW + 600);
+        let detector = TokenDetector::new(3600, || now.get());
+        assert_eq!(Some(NOW as u32), detector.detect(&token));
+        assert_eq!(Some(NOW as u32), detector.detect(&token[..6]));
+
+        // too late
+        now.set(NOW + 7200);
+        assert_eq!(None, detector.detect(&token));
+
+        // or from the service's clock running ahead of ours
+        now.set(NOW - 30);
+        assert_eq!(Some(NOW as u32), detector.detect(&token));
+    }
+
+    #[test]
+    fn test_not_mt() {
+        let detector = TokenDetector::new(3600, || NOW);
+        let mut rng = thread_rng();
+        for _ in 0..10 {
+            let token = rng.gen_iter::<u8>().take(16).collect::<Vec<u8>>().to_hex();
+            assert_eq!(None, detector.detect(&token));
+        }
+        assert_eq!(None, detector.detect("not hex"));
+        assert_eq!(None, detector.detect(""));
+
+        // MT, but seeded with something other than the time
+        assert_eq!(None, detector.detect(&token_bytes(12345, 16).to_hex()));
+    }
+}
@@ -0,0 +1,194 @@
+use std::collections::HashMap;
+
+use rand::{Rng, weak_rng};
+
+use md4::Md4State;
+use sha1::Sha1State;
+use util::write_u64_be;
+
+/// A Merkle-Damgard hash with a chaining value of at most 64 bits. Generic
+/// attacks (multicollisions, expandable messages, herding) cost around
+/// 2^(bits/2) compressions, so a small state makes them practical to run.
+pub trait IteratedHash: Sync {
+    fn state_bits(&self) -> usize;
+    fn initial_state(&self) -> u64;
+    fn compress(&self, state: u64, block: &[u8]) -> u64;
+
+    fn block_size(&self) -> usize { 64 }
+
+    fn state_mask(&self) -> u64 {
+        if self.state_bits() >= 64 {
+            !0
+        } else {
+            (1 << self.state_bits()) - 1
+        }
+    }
+
+    /// Run the compression function over every full block of `data`.
+    fn chain(&self, mut state: u64, data: &[u8]) -> u64 {
+        let bs = self.block_size();
+        assert!(data.len() % bs == 0, "data must be a multiple of the block size");
+        for block in data.chunks(bs) {
+            state = self.compress(state, block);
+        }
+        state
+    }
+
+    /// The chaining value after each full block of `msg`, starting from the
+    /// initial state. Entry `i` is the state after `i + 1` blocks.
+    fn chaining_states(&self, msg: &[u8]) -> Vec<u64> {
+        let bs = self.block_size();
+        let mut state = self.initial_state();
+        msg.chunks(bs)
+            .take_while(|block| block.len() == bs)
+            .map(|block| {
+                state = self.compress(state, block);
+                state
+            })
+            .collect()
+    }
+
+    /// Absorb the trailing partial block and MD strengthening padding for a
+    /// message of `len` bytes whose full blocks have been chained into
+    /// `state`.
+    fn finalize(&self, state: u64, tail: &[u8], len: u64) -> u64 {
+        let bs = self.block_size();
+        assert!(tail.len() < bs);
+        let mut last = tail.to_vec();
+        last.push(0x80);
+        while last.len() % bs != bs - 8 {
+            last.push(0);
+        }
+        let mut ml_bytes = [0_u8; 8];
+        write_u64_be(&mut ml_bytes, len << 3);
+        last.extend_from_slice(&ml_bytes);
+        self.chain(state, &last)
+    }
+
+    fn hash(&self, msg: &[u8]) -> u64 {
+        let bs = self.block_size();
+        let full = msg.len() - msg.len() % bs;
+        let state = self.chain(self.initial_state(), &msg[..full]);
+        self.finalize(state, &msg[full..], msg.len() as u64)
+    }
+}
+
+/// The SHA-1 compression function with its output truncated to `bits`. The
+/// chaining value is xored into the standard IV before each block.
+#[derive(Copy, Clone)]
+pub struct ToySha1 {
+    pub bits: usize,
+}
+
+impl IteratedHash for ToySha1 {
+    fn state_bits(&self) -> usize { self.bits }
+
+    fn initial_state(&self) -> u64 { 0 }
+
+    fn compress(&self, state: u64, block: &[u8]) -> u64 {
+        let mut s = Sha1State::new();
+        s.state[0] ^= state as u32;
+        s.state[1] ^= (state >> 32) as u32;
+        s.process(block);
+        ((s.state[0] as u64) | ((s.state[1] as u64) << 32)) & self.state_mask()
+    }
+}
+
+/// The MD4 compression function truncated to `bits`, built like `ToySha1`.
+#[derive(Copy, Clone)]
+pub struct ToyMd4 {
+    pub bits: usize,
+}
+
+impl IteratedHash for ToyMd4 {
+    fn state_bits(&self) -> usize { self.bits }
+
+    fn initial_state(&self) -> u64 { 0 }
+
+    fn compress(&self, state: u64, block: &[u8]) -> u64 {
+        let mut s = Md4State::new();
+        s.state[0] ^= state as u32;
+        s.state[1] ^= (state >> 32) as u32;
+        s.process(block);
+        ((s.state[0] as u64) | ((s.state[1] as u64) << 32)) & self.state_mask()
+    }
+}
+
+/// A pair of single blocks that collide from (possibly different) starting
+/// states.
+pub struct BlockCollision {
+    pub block_a: Vec<u8>,
+    pub block_b: Vec<u8>,
+    pub state: u64,
+}
+
+/// Birthday search for blocks `m_a`, `m_b` with
+/// `compress(state_a, m_a) == compress(state_b, m_b)`. Takes around
+/// 2^(bits/2) compressions from each side.
+pub fn find_block_collision<H, R>(h: &H, rng: &mut R, state_a: u64, state_b: u64)
+    -> BlockCollision
+    where H: IteratedHash + ?Sized, R: Rng
+{
+    let bs = h.block_size();
+    let mut seen_a: HashMap<u64, Vec<u8>> = HashMap::new();
+    let mut seen_b: HashMap<u64, Vec<u8>> = HashMap::new();
+    let mut block = vec![0_u8; bs];
+
+    loop {
+        rng.fill_bytes(&mut block);
+        let s = h.compress(state_a, &block);
+        if let Some(block_b) = seen_b.get(&s) {
+            if *block_b != block || state_a != state_b {
+                return BlockCollision { block_a: block.clone(), block_b: block_b.clone(), state: s };
+            }
+        }
+        seen_a.insert(s, block.clone());
+
+        rng.fill_bytes(&mut block);
+        let s = h.compress(state_b, &block);
+        if let Some(block_a) = seen_a.get(&s) {
+            if *block_a != block || state_a != state_b {
+                return BlockCollision { block_a: block_a.clone(), block_b: block.clone(), state: s };
+            }
+        }
+        seen_b.insert(s, block.clone());
+    }
+}
+
+pub fn find_collision<H: IteratedHash + ?Sized>(h: &H, state_a: u64, state_b: u64) -> BlockCollision {
+    find_block_collision(h, &mut weak_rng(), state_a, state_b)
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{Rng, weak_rng};
+
+    use super::{IteratedHash, ToySha1, ToyMd4, find_collision};
+
+    #[test]
+    fn test_chaining_states() {
+        let h = ToySha1 { bits: 24 };
+        let mut rng = weak_rng();
+        let mut msg = vec![0_u8; 64 * 5 + 10];
+        rng.fill_bytes(&mut msg);
+
+        let states = h.chaining_states(&msg);
+        assert_eq!(5, states.len());
+        assert_eq!(states[2], h.chain(h.initial_state(), &msg[0..192]));
+        assert!(states.iter().all(|&s| s < (1 << 24)));
+        assert_eq!(h.hash(&msg), h.finalize(states[4], &msg[320..], msg.len() as u64));
+    }
+
+    #[test]
+    fn test_find_collision() {
+        let h = ToyMd4 { bits: 20 };
+        let c = find_collision(&h, 0x1234, 0x1234);
+        assert!(c.block_a != c.block_b);
+        assert_eq!(h.compress(0x1234, &c.block_a), c.state);
+        assert_eq!(h.compress(0x1234, &c.block_b), c.state);
+
+        let c = find_collision(&h, 0x1234, 0xabcd);
+        assert_eq!(h.compress(0x1234, &c.block_a), c.state);
+        assert_eq!(h.compress(0xabcd, &c.block_b), c.state);
+    }
+}
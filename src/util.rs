@@ -1,3 +1,4 @@
+use std::cmp;
 use std::iter::FromIterator;
 use std::num::Wrapping as w;
 use std::mem;
@@ -119,6 +120,46 @@ impl MT19937Rng {
         }
     }
 
+    /// Seed from an array of words with the reference `init_by_array`, as
+    /// CPython and numpy do.
+    pub fn from_seed_array(key: &[u32]) -> MT19937Rng {
+        let mut rng = MT19937Rng::new_unseeded();
+        rng.reseed_array(key);
+        rng
+    }
+
+    pub fn reseed_array(&mut self, key: &[u32]) {
+        assert!(!key.is_empty(), "init_by_array needs at least one word");
+        self.reseed(19650218);
+        let (mut i, mut j) = (1, 0);
+        for _ in 0..cmp::max(MN, key.len()) {
+            let x_p = self.X[i - 1];
+            self.X[i] = (self.X[i] ^ ((x_p ^ (x_p >> (MW - 2))) * w(1664525))) +
+                w(key[j]) + w(j as u32);
+            i += 1;
+            j += 1;
+            if i >= MN {
+                self.X[0] = self.X[MN - 1];
+                i = 1;
+            }
+            if j >= key.len() {
+                j = 0;
+            }
+        }
+        for _ in 0..MN - 1 {
+            let x_p = self.X[i - 1];
+            self.X[i] = (self.X[i] ^ ((x_p ^ (x_p >> (MW - 2))) * w(1566083941))) -
+                w(i as u32);
+            i += 1;
+            if i >= MN {
+                self.X[0] = self.X[MN - 1];
+                i = 1;
+            }
+        }
+        self.X[0] = MPU;
+        self.i = MN;
+    }
+
     #[allow(bad_style)]
     fn twist(&mut self) {
         for j in 0..MN {
@@ -129,6 +170,110 @@ impl MT19937Rng {
         }
         self.i = 0;
     }
+
+    /// Inverse of `twist`. Every new word `X'[j]` gives away the top bit of
+    /// `X[j]` and the low 31 bits of `X[j + 1]`. The low bits of `X[0]`
+    /// aren't part of any of those, but if this state came out of a twist
+    /// itself they can be read off its own last word the same way.
+    fn untwist(&mut self) {
+        let new = self.X;
+        let mut old = [w(0_u32); MN];
+        for j in (0..MN).rev() {
+            let v = if j + MM >= MN { new[j + MM - MN] } else { old[j + MM] };
+            let x = untwist_word(new[j] ^ v);
+            old[j] = old[j] | (x & MPU);
+            if j + 1 < MN {
+                old[j + 1] = old[j + 1] | (x & MPL);
+            }
+        }
+        let x = untwist_word(old[MN - 1] ^ old[MM - 1]);
+        old[0] = old[0] | (x & MPL);
+
+        self.X = old;
+        self.i = MN;
+    }
+
+    /// Step the generator back `n` outputs, so the next `n` outputs repeat
+    /// the last `n`. Rewinding past the seeding gives garbage.
+    pub fn rewind(&mut self, mut n: usize) {
+        while n > 0 {
+            if self.i == 0 {
+                self.untwist();
+            }
+            let step = cmp::min(n, self.i);
+            self.i -= step;
+            n -= step;
+        }
+    }
+
+    /// Walk back at most `max_outputs` outputs looking for the state
+    /// `reseed` leaves behind, and return the seed along with how many
+    /// outputs have been generated since. Works for clones taken at any
+    /// offset into the stream.
+    #[allow(bad_style)]
+    pub fn recover_seed(&self, max_outputs: u64) -> Option<(u32, u64)> {
+        // `X` holds the words x[m..m + 624] of the output stream, where
+        // outputs are temper(x[624]), temper(x[625]), ... For a seeded
+        // generator x[0..624] follows `reseed`, so look for x[0] in each
+        // window, using the later window to check the words after it.
+        let mut rng = self.clone();
+        let mut later = self.clone();
+        later.twist();
+        // m = -p0 - 624 * level, relative to x[0] at position p0
+        let levels = max_outputs / MN as u64 + 2;
+
+        for level in 0..levels + 1 {
+            let mut words = rng.X.to_vec();
+            words.extend_from_slice(&later.X);
+            for p0 in 0..MN {
+                if (2..6).all(|j| words[p0 + j] == init_step(words[p0 + j - 1], j)) {
+                    let seed = seed_from_x1(words[p0 + 1]);
+                    // next output is x[m0 + i], and the first one is x[624]
+                    let pos = (MN as u64 * level) as i64 - p0 as i64 +
+                        self.i as i64 - MN as i64;
+                    if pos < 0 || pos as u64 > max_outputs {
+                        return None;
+                    }
+                    return Some((seed, pos as u64));
+                }
+            }
+            later = rng.clone();
+            rng.untwist();
+        }
+        None
+    }
+}
+
+// Recover `(x >> 1) ^ (MA * (x & 1))`'s input `x`, using the fact that the
+// top bit of the output is just the low bit of `x`.
+fn untwist_word(y: w32) -> w32 {
+    if y & MPU != w(0) {
+        ((y ^ MA) << 1) | M1
+    } else {
+        y << 1
+    }
+}
+
+// One step of `reseed`, computing x[j] from x[j - 1].
+fn init_step(x_p: w32, j: usize) -> w32 {
+    MF * (x_p ^ (x_p >> (MW - 2))) + w(j as u32)
+}
+
+// Invert the first step of `reseed`: x[1] = MF * (x[0] ^ (x[0] >> 30)) + 1.
+fn seed_from_x1(x1: w32) -> u32 {
+    // MF is odd, so Newton's iteration finds its inverse mod 2^32
+    let mut inv = MF;
+    for _ in 0..5 {
+        inv = inv * (w(2) - MF * inv);
+    }
+    let y = (x1 - w(1)) * inv;
+    unshift_right(y.0, MW - 2, !0)
+}
+
+impl Clone for MT19937Rng {
+    fn clone(&self) -> MT19937Rng {
+        MT19937Rng::from_state(self.i, self.X)
+    }
 }
 
 impl SeedableRng<u32> for MT19937Rng {
@@ -154,14 +299,124 @@ impl Rng for MT19937Rng {
             self.twist();
         }
 
-        let mut y = self.X[self.i];
-        y = y ^ (y >> MU);
-        y = y ^ ((y << MS) & MB);
-        y = y ^ ((y << MT) & MC);
-        y = y ^ (y >> ML);
-
+        let y = temper(self.X[self.i].0);
         self.i += 1;
-        y.0
+        y
+    }
+}
+
+/// The MT19937 output tempering.
+pub fn temper(x: u32) -> u32 {
+    let mut y = w(x);
+    y = y ^ (y >> MU);
+    y = y ^ ((y << MS) & MB);
+    y = y ^ ((y << MT) & MC);
+    y = y ^ (y >> ML);
+    y.0
+}
+
+// Invert `y = x ^ ((x >> shift) & mask)`: each pass fixes another `shift`
+// bits, starting from the top.
+fn unshift_right(y: u32, shift: usize, mask: u32) -> u32 {
+    let mut x = y;
+    for _ in 0..(32 / shift) {
+        x = y ^ ((x >> shift) & mask);
+    }
+    x
+}
+
+// Invert `y = x ^ ((x << shift) & mask)`, starting from the bottom bits.
+fn unshift_left(y: u32, shift: usize, mask: u32) -> u32 {
+    let mut x = y;
+    for _ in 0..(32 / shift) {
+        x = y ^ ((x << shift) & mask);
+    }
+    x
+}
+
+/// Invert the MT19937 output tempering, recovering the state word.
+pub fn untemper(y: u32) -> u32 {
+    let y = unshift_right(y, ML, !0);
+    let y = unshift_left(y, MT, MC.0);
+    let y = unshift_left(y, MS, MB.0);
+    unshift_right(y, MU, !0)
+}
+
+/// Clones an `MT19937Rng` from its outputs, fed in one at a time.
+///
+/// Any 624 consecutive outputs determine the rest of the stream: untempered,
+/// they are a window of the twist recurrence, no matter where the window
+/// starts relative to a twist. Outputs that weren't observed can be stepped
+/// over with `skip`, which restarts the window if it isn't full yet. Once
+/// synchronized, further outputs are checked against the clone, and a
+/// mismatch (say, the target reseeded) starts over.
+pub struct MT19937Cloner {
+    window: Vec<w32>,
+    clone: Option<MT19937Rng>,
+    mismatches: usize,
+}
+
+impl MT19937Cloner {
+    pub fn new() -> MT19937Cloner {
+        MT19937Cloner {
+            window: Vec::with_capacity(MN),
+            clone: None,
+            mismatches: 0,
+        }
+    }
+
+    /// Feed the next output. Returns whether the clone is synchronized.
+    pub fn push(&mut self, y: u32) -> bool {
+        if let Some(mut rng) = self.clone.take() {
+            if rng.next_u32() == y {
+                self.clone = Some(rng);
+                return true;
+            }
+            self.mismatches += 1;
+        }
+
+        self.window.push(w(untemper(y)));
+        if self.window.len() == MN {
+            let mut state = [w(0_u32); MN];
+            state.copy_from_slice(&self.window);
+            self.clone = Some(MT19937Rng::from_state(MN, state));
+            self.window.clear();
+            return true;
+        }
+        false
+    }
+
+    /// Step over `n` outputs that weren't observed.
+    pub fn skip(&mut self, n: usize) {
+        match self.clone {
+            Some(ref mut rng) => {
+                for _ in 0..n {
+                    rng.next_u32();
+                }
+            },
+            None => if n > 0 {
+                self.window.clear();
+            },
+        }
+    }
+
+    pub fn is_ready(&self) -> bool {
+        self.clone.is_some()
+    }
+
+    /// How many more consecutive outputs are needed.
+    pub fn needed(&self) -> usize {
+        if self.is_ready() { 0 } else { MN - self.window.len() }
+    }
+
+    /// How many times an output contradicted the clone.
+    pub fn mismatches(&self) -> usize {
+        self.mismatches
+    }
+
+    /// A generator whose next output is the one after the last one seen.
+    pub fn rng(&self) -> Option<MT19937Rng> {
+        self.clone.clone()
     }
 }
 
@@ -172,6 +427,152 @@ impl Rand for MT19937Rng {
     }
 }
 
+#[allow(bad_style)]
+pub type w64 = w<u64>;
+
+// Mersenne Twister 19937-64 Constants
+const M64W: usize = 64;
+const M64U: usize = 29;
+const M64S: usize = 17;
+const M64T: usize = 37;
+const M64L: usize = 43;
+const M64N: usize = 312;
+const M64M: usize = 156;
+const M64A: w64 = w(0xB5026F5AA96619E9_u64);
+const M64D: w64 = w(0x5555555555555555_u64);
+const M64B: w64 = w(0x71D67FFFEDA60000_u64);
+const M64C: w64 = w(0xFFF7EEE000000000_u64);
+const M64F: w64 = w(6364136223846793005_u64);
+const M64PU: w64 = w(0xFFFFFFFF80000000_u64);
+const M64PL: w64 = w(0x000000007FFFFFFF_u64);
+
+/// The 64-bit Mersenne Twister (`std::mt19937_64`).
+#[allow(bad_style)]
+pub struct MT19937_64Rng {
+    i: usize,
+    X: [w64; M64N],
+}
+
+impl MT19937_64Rng {
+    pub fn new_unseeded() -> MT19937_64Rng {
+        let mut rng = MT19937_64Rng {
+            i: M64N,
+            X: [w(0_u64); M64N]
+        };
+        rng.reseed(5489);
+        rng
+    }
+
+    #[allow(bad_style)]
+    pub fn from_state(i: usize, X: [w64; M64N]) -> MT19937_64Rng {
+        MT19937_64Rng {
+            i: i,
+            X: X,
+        }
+    }
+
+    #[allow(bad_style)]
+    fn twist(&mut self) {
+        for j in 0..M64N {
+            let x = (self.X[j] & M64PU) +
+                (self.X[(j + 1) % M64N] & M64PL);
+            let xA = (x >> 1) ^ (M64A * (x & w(1)));
+            self.X[j] = self.X[(j + M64M) % M64N] ^ xA;
+        }
+        self.i = 0;
+    }
+}
+
+impl Clone for MT19937_64Rng {
+    fn clone(&self) -> MT19937_64Rng {
+        MT19937_64Rng::from_state(self.i, self.X)
+    }
+}
+
+impl SeedableRng<u64> for MT19937_64Rng {
+    fn reseed(&mut self, seed: u64) {
+        self.i = M64N;
+        self.X[0] = w(seed);
+        for j in 1..M64N {
+            let x_p = self.X[j - 1];
+            self.X[j] = M64F * (x_p ^ (x_p >> (M64W - 2))) + w(j as u64);
+        }
+    }
+
+    fn from_seed(seed: u64) -> MT19937_64Rng {
+        let mut rng = MT19937_64Rng::new_unseeded();
+        rng.reseed(seed);
+        rng
+    }
+}
+
+impl Rng for MT19937_64Rng {
+    fn next_u32(&mut self) -> u32 {
+        (self.next_u64() >> 32) as u32
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        if self.i >= M64N {
+            self.twist();
+        }
+
+        let mut y = self.X[self.i];
+        y = y ^ ((y >> M64U) & M64D);
+        y = y ^ ((y << M64S) & M64B);
+        y = y ^ ((y << M64T) & M64C);
+        y = y ^ (y >> M64L);
+
+        self.i += 1;
+        y.0
+    }
+}
+
+impl Rand for MT19937_64Rng {
+    fn rand<R: Rng>(rng: &mut R) -> MT19937_64Rng {
+        let seed: u64 = rng.gen();
+        MT19937_64Rng::from_seed(seed)
+    }
+}
+
+// Invert `y = x ^ ((x >> shift) & mask)`: each pass fixes another `shift`
+// bits, starting from the top.
+fn unshift_right_64(y: u64, shift: usize, mask: u64) -> u64 {
+    let mut x = y;
+    for _ in 0..(64 / shift) {
+        x = y ^ ((x >> shift) & mask);
+    }
+    x
+}
+
+// Invert `y = x ^ ((x << shift) & mask)`, starting from the bottom bits.
+fn unshift_left_64(y: u64, shift: usize, mask: u64) -> u64 {
+    let mut x = y;
+    for _ in 0..(64 / shift) {
+        x = y ^ ((x << shift) & mask);
+    }
+    x
+}
+
+/// Invert the MT19937-64 output tempering, recovering the state word.
+pub fn untemper64(y: u64) -> u64 {
+    let y = unshift_right_64(y, M64L, !0);
+    let y = unshift_left_64(y, M64T, M64C.0);
+    let y = unshift_left_64(y, M64S, M64B.0);
+    unshift_right_64(y, M64U, M64D.0)
+}
+
+/// Clone an MT19937-64 from any 312 consecutive outputs: untempered, they
+/// are a window of the twist recurrence wherever it starts, as with
+/// `MT19937Cloner`. The clone continues where the observed outputs stop.
+pub fn clone_mt19937_64(outputs: &[u64]) -> MT19937_64Rng {
+    assert_eq!(M64N, outputs.len(), "need {} consecutive outputs", M64N);
+    let mut state = [w(0_u64); M64N];
+    for (x, &y) in state.iter_mut().zip(outputs.iter()) {
+        *x = w(untemper64(y));
+    }
+    MT19937_64Rng::from_state(M64N, state)
+}
+
 pub struct PRNGKeystream<'a, R: 'a> {
     rng: &'a mut R,
     curr: u32,
@@ -485,3 +886,156 @@ fn test_bytes_hamming_dist() {
     assert_eq!(37, bytes_hamming_dist(&b1, &b2))
 }
 
+#[test]
+fn test_mt19937_64() {
+    // outputs of std::mt19937_64 with the default seed and an explicit one
+    let mut rng = MT19937_64Rng::new_unseeded();
+    let expected = [14514284786278117030, 4620546740167642908, 13109570281517897720,
+                    17462938647148434322, 355488278567739596];
+    for &x in expected.iter() {
+        assert_eq!(x, rng.next_u64());
+    }
+
+    let mut rng = MT19937_64Rng::from_seed(0x123456789abcdef);
+    let expected = [13169394222641354532, 341231063478520994, 9087906688904613890,
+                    14133500640045507207, 10409822327491941954];
+    for &x in expected.iter() {
+        assert_eq!(x, rng.next_u64());
+    }
+
+    // the C++ standard requires this of the 10000th default output
+    let mut rng = MT19937_64Rng::new_unseeded();
+    for _ in 0..9999 {
+        rng.next_u64();
+    }
+    assert_eq!(9981545732273789042, rng.next_u64());
+}
+
+#[test]
+fn test_clone_mt19937_64() {
+    let mut rng = MT19937_64Rng::from_seed(0xdeadbeef);
+    for _ in 0..1000 {
+        let x = rng.next_u64();
+        let y = untemper64(x);
+        let mut clone = MT19937_64Rng::from_state(0, [w(y); M64N]);
+        assert_eq!(x, clone.next_u64());
+    }
+
+    // 1000 outputs in, so the window doesn't line up with a twist
+    let outputs = (0..M64N).map(|_| rng.next_u64()).collect::<Vec<_>>();
+    let mut clone = clone_mt19937_64(&outputs);
+    for _ in 0..1000 {
+        assert_eq!(rng.next_u64(), clone.next_u64());
+    }
+    let mut copy = clone.clone();
+    assert_eq!(clone.next_u64(), copy.next_u64());
+}
+
+#[test]
+fn test_mt19937_init_by_array() {
+    // mt19937ar.out from the reference implementation
+    let mut rng = MT19937Rng::from_seed_array(&[0x123, 0x234, 0x345, 0x456]);
+    let expected = [1067595299, 955945823, 477289528, 4107218783, 4228976476];
+    for &x in expected.iter() {
+        assert_eq!(x, rng.next_u32());
+    }
+}
+
+#[test]
+fn test_untemper() {
+    let mut rng = MT19937Rng::from_seed(0x12345678_u32);
+    for _ in 0..1000 {
+        let y = rng.next_u32();
+        assert_eq!(y, temper(untemper(y)));
+    }
+}
+
+#[test]
+fn test_mt19937_cloner() {
+    // start at an arbitrary offset into the stream
+    let mut rng = MT19937Rng::from_seed(0xcafebabe_u32);
+    for _ in 0..1000 {
+        rng.next_u32();
+    }
+
+    let mut cloner = MT19937Cloner::new();
+    // a gap before the window fills starts over
+    for _ in 0..100 {
+        assert!(!cloner.push(rng.next_u32()));
+    }
+    rng.next_u32();
+    cloner.skip(1);
+    assert_eq!(MN, cloner.needed());
+
+    for i in 0..MN {
+        assert_eq!(i == MN - 1, cloner.push(rng.next_u32()));
+    }
+
+    // a gap once synchronized is fine
+    rng.next_u32();
+    rng.next_u32();
+    cloner.skip(2);
+    assert!(cloner.push(rng.next_u32()));
+
+    let mut clone = cloner.rng().unwrap();
+    for _ in 0..2000 {
+        assert_eq!(rng.next_u32(), clone.next_u32());
+    }
+
+    // the target reseeds
+    let mut rng = MT19937Rng::from_seed(1_u32);
+    assert!(!cloner.push(rng.next_u32()));
+    assert_eq!(1, cloner.mismatches());
+}
+
+#[test]
+fn test_mt19937_rewind() {
+    let mut rng = MT19937Rng::from_seed(0x5eed_u32);
+    let first = (0..5000).map(|_| rng.next_u32()).collect::<Vec<_>>();
+
+    // back across several twists, and forward again
+    rng.rewind(4000);
+    for &y in first[1000..].iter() {
+        assert_eq!(y, rng.next_u32());
+    }
+    rng.rewind(5000);
+    for &y in first.iter() {
+        assert_eq!(y, rng.next_u32());
+    }
+    for _ in 0..3 {
+        rng.rewind(1);
+        assert_eq!(first[4999], rng.next_u32());
+    }
+}
+
+#[test]
+fn test_mt19937_recover_seed() {
+    let rng = MT19937Rng::from_seed(0x5eed_u32);
+    assert_eq!(Some((0x5eed, 0)), rng.recover_seed(0));
+
+    for &skip in [0_usize, 1, 623, 624, 625, 2000, 3120].iter() {
+        let mut rng = MT19937Rng::from_seed(0xdecafbad_u32);
+        for _ in 0..skip {
+            rng.next_u32();
+        }
+
+        // clone from an arbitrary window, then walk the clone back
+        let mut cloner = MT19937Cloner::new();
+        for _ in 0..MN {
+            cloner.push(rng.next_u32());
+        }
+        let clone = cloner.rng().unwrap();
+        assert_eq!(Some((0xdecafbad, (skip + MN) as u64)), clone.recover_seed(10000));
+        if skip + MN > 1000 {
+            assert_eq!(None, clone.recover_seed(1000));
+        }
+
+        // and reproduce the outputs from before the ones we saw
+        let mut clone = clone;
+        clone.rewind(skip + MN);
+        let mut fresh = MT19937Rng::from_seed(0xdecafbad_u32);
+        for _ in 0..skip + MN {
+            assert_eq!(fresh.next_u32(), clone.next_u32());
+        }
+    }
+}
@@ -0,0 +1,312 @@
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{self, Read};
+use std::panic::{self, AssertUnwindSafe};
+use std::path::Path;
+
+use rust_crypto::hmac::Hmac;
+use rust_crypto::mac::Mac;
+use encoding::FromHex;
+use serialize::json::Json;
+
+use crypto::cbc;
+use sha1::Sha1;
+
+/// Harness for Project Wycheproof test vector files.
+///
+/// A file has a top-level `algorithm` and a list of `testGroups`, each with
+/// group-wide parameters (key size, tag size, ...) and a list of `tests`.
+/// Every test carries an expected `result` of `valid`, `invalid` or
+/// `acceptable` and a list of `flags` naming the edge case it exercises.
+/// Primitives plug in by implementing `Adapter` for their algorithm name.
+pub trait Adapter {
+    /// The `algorithm` value of the files this adapter can run.
+    fn algorithm(&self) -> &str;
+
+    /// Run a single test and report whether the primitive accepted it
+    /// (produced the expected output, verified the tag or signature).
+    fn run(&self, case: &TestCase) -> Verdict;
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Verdict {
+    Accepted,
+    Rejected,
+    /// The adapter doesn't support these parameters (e.g. the key size).
+    Skipped(String),
+}
+
+pub struct TestCase<'a> {
+    pub group: &'a Json,
+    pub test: &'a Json,
+}
+
+impl<'a> TestCase<'a> {
+    pub fn id(&self) -> u64 {
+        self.test.find("tcId").and_then(|v| v.as_u64()).unwrap_or(0)
+    }
+
+    pub fn result(&self) -> &str {
+        self.test.find("result").and_then(|v| v.as_string()).unwrap_or("")
+    }
+
+    pub fn comment(&self) -> &str {
+        self.test.find("comment").and_then(|v| v.as_string()).unwrap_or("")
+    }
+
+    pub fn flags(&self) -> Vec<String> {
+        self.test.find("flags")
+            .and_then(|v| v.as_array())
+            .map(|a| a.iter().filter_map(|f| f.as_string()).map(|f| f.to_string()).collect())
+            .unwrap_or(Vec::new())
+    }
+
+    /// A hex-encoded field of the test.
+    pub fn hex(&self, key: &str) -> Option<Vec<u8>> {
+        self.test.find(key)
+            .and_then(|v| v.as_string())
+            .and_then(|s| s.from_hex().ok())
+    }
+
+    /// An integer parameter of the enclosing test group.
+    pub fn group_int(&self, key: &str) -> Option<u64> {
+        self.group.find(key).and_then(|v| v.as_u64())
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Tally {
+    pub passed: usize,
+    pub failed: usize,
+    pub skipped: usize,
+}
+
+#[derive(Debug)]
+pub struct Failure {
+    pub id: u64,
+    pub result: String,
+    pub comment: String,
+    pub flags: Vec<String>,
+    pub reason: String,
+}
+
+/// Outcomes tallied by expected result and by flag.
+#[derive(Debug, Default)]
+pub struct Report {
+    pub by_result: BTreeMap<String, Tally>,
+    pub by_flag: BTreeMap<String, Tally>,
+    pub failures: Vec<Failure>,
+    /// How many tests the adapter skipped, by the reason it gave.
+    pub skips: BTreeMap<String, usize>,
+}
+
+impl Report {
+    pub fn total(&self) -> Tally {
+        self.by_result.values().fold(Tally::default(), |acc, t| Tally {
+            passed: acc.passed + t.passed,
+            failed: acc.failed + t.failed,
+            skipped: acc.skipped + t.skipped,
+        })
+    }
+
+    fn record<F: Fn(&mut Tally)>(&mut self, case: &TestCase, f: F) {
+        f(self.by_result.entry(case.result().to_string()).or_insert(Tally::default()));
+        for flag in case.flags() {
+            f(self.by_flag.entry(flag).or_insert(Tally::default()));
+        }
+    }
+}
+
+pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Json> {
+    let mut f = try!(File::open(path));
+    let mut s = String::new();
+    try!(f.read_to_string(&mut s));
+    Json::from_str(&s).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))
+}
+
+/// Run every test in `doc` through the adapter registered for its
+/// algorithm. Panics inside the primitive are caught and counted as
+/// failures.
+pub fn run(doc: &Json, adapters: &[&Adapter]) -> Result<Report, String> {
+    let algorithm = try!(doc.find("algorithm")
+        .and_then(|v| v.as_string())
+        .ok_or("missing algorithm".to_string()));
+    let adapter = try!(adapters.iter()
+        .find(|a| a.algorithm() == algorithm)
+        .ok_or(format!("no adapter for {}", algorithm)));
+    let groups = try!(doc.find("testGroups")
+        .and_then(|v| v.as_array())
+        .ok_or("missing testGroups".to_string()));
+
+    let mut report = Report::default();
+
+    for group in groups {
+        let tests = match group.find("tests").and_then(|v| v.as_array()) {
+            Some(tests) => tests,
+            None => continue,
+        };
+        for test in tests {
+            let case = TestCase { group: group, test: test };
+            let verdict = panic::catch_unwind(AssertUnwindSafe(|| adapter.run(&case)));
+
+            let failure = match (case.result(), verdict) {
+                (_, Ok(Verdict::Skipped(reason))) => {
+                    report.record(&case, |t| t.skipped += 1);
+                    *report.skips.entry(reason).or_insert(0) += 1;
+                    continue;
+                },
+                (_, Err(_)) => Some("panicked".to_string()),
+                ("valid", Ok(Verdict::Rejected)) => Some("rejected a valid test".to_string()),
+                ("invalid", Ok(Verdict::Accepted)) => Some("accepted an invalid test".to_string()),
+                _ => None,
+            };
+
+            match failure {
+                Some(reason) => {
+                    report.record(&case, |t| t.failed += 1);
+                    report.failures.push(Failure {
+                        id: case.id(),
+                        result: case.result().to_string(),
+                        comment: case.comment().to_string(),
+                        flags: case.flags(),
+                        reason: reason,
+                    });
+                },
+                None => report.record(&case, |t| t.passed += 1),
+            }
+        }
+    }
+
+    Ok(report)
+}
+
+/// `mac_test_schema.json` files for HMAC over the crate's SHA-1.
+pub struct HmacSha1;
+
+impl Adapter for HmacSha1 {
+    fn algorithm(&self) -> &str { "HMACSHA1" }
+
+    fn run(&self, case: &TestCase) -> Verdict {
+        let (key, msg, tag) = match (case.hex("key"), case.hex("msg"), case.hex("tag")) {
+            (Some(key), Some(msg), Some(tag)) => (key, msg, tag),
+            _ => return Verdict::Skipped("malformed test".to_string()),
+        };
+        let tag_bytes = case.group_int("tagSize").unwrap_or(160) as usize / 8;
+
+        let mut hmac = Hmac::new(Sha1::new(), &key);
+        hmac.input(&msg);
+        let out = hmac.result();
+        let out = out.code();
+
+        if tag.len() == tag_bytes && &out[..tag_bytes] == &tag[..] {
+            Verdict::Accepted
+        } else {
+            Verdict::Rejected
+        }
+    }
+}
+
+/// `ind_cpa_test_schema.json` files for `crypto::cbc` with PKCS#7 padding.
+/// Only AES-128 is implemented; other key sizes are skipped, and show up in
+/// `Report::skips`.
+pub struct AesCbcPkcs5;
+
+impl Adapter for AesCbcPkcs5 {
+    fn algorithm(&self) -> &str { "AES-CBC-PKCS5" }
+
+    fn run(&self, case: &TestCase) -> Verdict {
+        let (key, iv, msg, ct) = match (case.hex("key"), case.hex("iv"), case.hex("msg"), case.hex("ct")) {
+            (Some(key), Some(iv), Some(msg), Some(ct)) => (key, iv, msg, ct),
+            _ => return Verdict::Skipped("malformed test".to_string()),
+        };
+        if key.len() != 16 {
+            return Verdict::Skipped(format!("{} bit keys", key.len() * 8));
+        }
+
+        match cbc::decrypt(&key, &iv, &ct) {
+            Some(ref pt) if *pt == msg && cbc::encrypt(&key, &iv, &msg) == ct => Verdict::Accepted,
+            _ => Verdict::Rejected,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serialize::json::Json;
+
+    use super::{load, run, Adapter, AesCbcPkcs5, HmacSha1, Report, TestCase, Verdict};
+
+    fn run_file(file: &str) -> Report {
+        let doc = load(format!("./data/wycheproof/{}", file)).unwrap();
+        let report = run(&doc, &[&HmacSha1, &AesCbcPkcs5]).unwrap();
+        for (result, tally) in &report.by_result {
+            println!("{} {}: {:?}", file, result, tally);
+        }
+        for failure in &report.failures {
+            println!("{} tcId {}: {} ({:?})", file, failure.id, failure.reason, failure.flags);
+        }
+        report
+    }
+
+    #[test]
+    fn test_hmac_sha1() {
+        let report = run_file("hmac_sha1_test.json");
+        let total = report.total();
+        assert!(report.failures.is_empty());
+        assert_eq!(0, total.skipped);
+        assert!(report.skips.is_empty());
+        assert!(report.by_result["valid"].passed > 0);
+        assert!(report.by_flag["ModifiedTag"].passed > 0);
+    }
+
+    #[test]
+    fn test_aes_cbc_pkcs5() {
+        let report = run_file("aes_cbc_pkcs5_test.json");
+        assert!(report.failures.is_empty());
+        assert!(report.by_result["invalid"].passed > 0);
+        // 256 bit keys aren't supported by crypto::cbc
+        assert_eq!(16, report.total().skipped);
+        assert_eq!(Some(&16), report.skips.get("256 bit keys"));
+        assert_eq!(1, report.skips.len());
+    }
+
+    struct Broken;
+
+    impl Adapter for Broken {
+        fn algorithm(&self) -> &str { "BROKEN" }
+
+        fn run(&self, case: &TestCase) -> Verdict {
+            match case.id() {
+                1 => Verdict::Accepted,
+                2 => Verdict::Accepted,
+                4 => Verdict::Skipped("unsupported".to_string()),
+                _ => panic!("boom"),
+            }
+        }
+    }
+
+    #[test]
+    fn test_report_failures() {
+        let doc = Json::from_str(r#"{
+            "algorithm": "BROKEN",
+            "testGroups": [{"tests": [
+                {"tcId": 1, "result": "valid", "flags": []},
+                {"tcId": 2, "result": "invalid", "flags": ["Foo"]},
+                {"tcId": 3, "result": "acceptable", "flags": ["Foo"]},
+                {"tcId": 4, "result": "valid", "flags": []}
+            ]}]
+        }"#).unwrap();
+
+        let report = run(&doc, &[&Broken]).unwrap();
+        assert_eq!(1, report.by_result["valid"].passed);
+        assert_eq!(1, report.by_result["invalid"].failed);
+        assert_eq!(1, report.by_result["acceptable"].failed);
+        assert_eq!(2, report.by_flag["Foo"].failed);
+        assert_eq!(vec![2, 3], report.failures.iter().map(|f| f.id).collect::<Vec<_>>());
+        assert_eq!("panicked", report.failures[1].reason);
+        assert_eq!(1, report.by_result["valid"].skipped);
+        assert_eq!(Some(&1), report.skips.get("unsupported"));
+
+        assert!(run(&doc, &[&HmacSha1]).is_err());
+    }
+}
@@ -0,0 +1,488 @@
+use rand::{Rng, SeedableRng, XorShiftRng};
+
+use gf2::{BitMatrix, BitVec};
+
+/// A generator whose state update and outputs are linear over GF(2), like
+/// every xorshift variant without a nonlinear output step. Whatever bits of
+/// its outputs leak are linear equations in the state bits, so enough of
+/// them pin the state down; see `recover`.
+pub trait LinearRng: Sized {
+    /// The size of the state in bits.
+    fn state_len() -> usize;
+
+    /// The generator with state bits `bits`, in whatever order the
+    /// implementation likes, as long as it's consistent.
+    fn from_state_bits(bits: &BitVec) -> Self;
+}
+
+/// Clone a linear generator from partial knowledge of its outputs.
+///
+/// `output` advances a generator and returns one output word, as the
+/// victim did; `observed[k]` is `(value, mask)` for the `k`th word, where
+/// only the bits set in `mask` were seen. Any linear function of the state
+/// will do as the output, e.g. reading a word and then stepping backwards.
+///
+/// The equations come from running `output` on a generator for each single
+/// state bit. Returns the generator after the last observation, or `None`
+/// if the observations don't determine the state or contradict each other.
+pub fn recover<R, F>(observed: &[(u64, u64)], mut output: F) -> Option<R>
+    where R: LinearRng,
+          F: FnMut(&mut R) -> u64
+{
+    let n = R::state_len();
+    let mut basis = (0..n).map(|i| {
+        let mut bits = BitVec::zeros(n);
+        bits.set(i, true);
+        R::from_state_bits(&bits)
+    }).collect::<Vec<_>>();
+
+    let mut rows = Vec::new();
+    let mut rhs = Vec::new();
+    for &(value, mask) in observed {
+        let words = basis.iter_mut().map(&mut output).collect::<Vec<_>>();
+        for j in (0..64).filter(|&j| mask >> j & 1 == 1) {
+            let row = words.iter().map(|&w| w >> j & 1 == 1).collect::<Vec<_>>();
+            rows.push(BitVec::from_bools(&row));
+            rhs.push(value >> j & 1 == 1);
+        }
+    }
+    if rows.len() < n {
+        return None;
+    }
+
+    let A = BitMatrix::from_rows(&rows);
+    if A.rank() < n {
+        return None;
+    }
+    let state = match A.solve(&BitVec::from_bools(&rhs)) {
+        Some(state) => state,
+        None => return None,
+    };
+
+    let mut rng = R::from_state_bits(&state);
+    for _ in observed {
+        output(&mut rng);
+    }
+    Some(rng)
+}
+
+fn word_bits(bits: &BitVec, offset: usize, len: usize) -> u64 {
+    (0..len).fold(0, |acc, i| acc | (bits.get(offset + i) as u64) << i)
+}
+
+/// Marsaglia's 32-bit xorshift with shifts 13, 17 and 5. The output is the
+/// whole state.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Xorshift32 {
+    state: u32,
+}
+
+impl Xorshift32 {
+    pub fn new(seed: u32) -> Xorshift32 {
+        assert!(seed != 0, "xorshift can't start from zero");
+        Xorshift32 { state: seed }
+    }
+
+    pub fn next(&mut self) -> u32 {
+        let mut x = self.state;
+        x ^= x << 13;
+        x ^= x >> 17;
+        x ^= x << 5;
+        self.state = x;
+        x
+    }
+}
+
+impl LinearRng for Xorshift32 {
+    fn state_len() -> usize {
+        32
+    }
+
+    fn from_state_bits(bits: &BitVec) -> Xorshift32 {
+        Xorshift32 { state: word_bits(bits, 0, 32) as u32 }
+    }
+}
+
+impl SeedableRng<u32> for Xorshift32 {
+    fn reseed(&mut self, seed: u32) {
+        *self = Xorshift32::new(seed);
+    }
+
+    fn from_seed(seed: u32) -> Xorshift32 {
+        Xorshift32::new(seed)
+    }
+}
+
+impl Rng for Xorshift32 {
+    fn next_u32(&mut self) -> u32 {
+        self.next()
+    }
+}
+
+/// Marsaglia's 64-bit xorshift with shifts 13, 7 and 17.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Xorshift64 {
+    state: u64,
+}
+
+impl Xorshift64 {
+    pub fn new(seed: u64) -> Xorshift64 {
+        assert!(seed != 0, "xorshift can't start from zero");
+        Xorshift64 { state: seed }
+    }
+
+    pub fn next(&mut self) -> u64 {
+        let mut x = self.state;
+        x ^= x << 13;
+        x ^= x >> 7;
+        x ^= x << 17;
+        self.state = x;
+        x
+    }
+}
+
+impl LinearRng for Xorshift64 {
+    fn state_len() -> usize {
+        64
+    }
+
+    fn from_state_bits(bits: &BitVec) -> Xorshift64 {
+        Xorshift64 { state: word_bits(bits, 0, 64) }
+    }
+}
+
+impl SeedableRng<u64> for Xorshift64 {
+    fn reseed(&mut self, seed: u64) {
+        *self = Xorshift64::new(seed);
+    }
+
+    fn from_seed(seed: u64) -> Xorshift64 {
+        Xorshift64::new(seed)
+    }
+}
+
+impl Rng for Xorshift64 {
+    fn next_u32(&mut self) -> u32 {
+        (self.next() >> 32) as u32
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.next()
+    }
+}
+
+/// rand's `XorShiftRng`, which `weak_rng()` returns: 128 bits of state in
+/// four words, whose newest word is the output. Four whole outputs are the
+/// state; partial ones (bytes from `gen::<u8>()`, say) work too.
+impl LinearRng for XorShiftRng {
+    fn state_len() -> usize {
+        128
+    }
+
+    fn from_state_bits(bits: &BitVec) -> XorShiftRng {
+        let mut seed = [0_u32; 4];
+        for (i, word) in seed.iter_mut().enumerate() {
+            *word = word_bits(bits, 32 * i, 32) as u32;
+        }
+        XorShiftRng::from_seed(seed)
+    }
+}
+
+/// Invert `x ^= x >> shift`.
+fn unshift_right(y: u64, shift: usize) -> u64 {
+    let mut x = y;
+    for _ in 0..64 / shift {
+        x = y ^ (x >> shift);
+    }
+    x
+}
+
+/// Invert `x ^= x << shift`.
+fn unshift_left(y: u64, shift: usize) -> u64 {
+    let mut x = y;
+    for _ in 0..64 / shift {
+        x = y ^ (x << shift);
+    }
+    x
+}
+
+/// xorshift128+, as in V8 (and the other browsers until 2018). The state is
+/// linear, but the `state0 + state1` output is not; V8's `Math.random()`
+/// doesn't use it though, see `V8MathRandom`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Xorshift128Plus {
+    pub state0: u64,
+    pub state1: u64,
+}
+
+impl Xorshift128Plus {
+    pub fn new(state0: u64, state1: u64) -> Xorshift128Plus {
+        assert!(state0 != 0 || state1 != 0, "xorshift can't start from zero");
+        Xorshift128Plus {
+            state0: state0,
+            state1: state1,
+        }
+    }
+
+    /// Advance the state, without producing an output.
+    pub fn step(&mut self) {
+        let mut s1 = self.state0;
+        let s0 = self.state1;
+        self.state0 = s0;
+        s1 ^= s1 << 23;
+        s1 ^= s1 >> 17;
+        s1 ^= s0;
+        s1 ^= s0 >> 26;
+        self.state1 = s1;
+    }
+
+    /// Undo a `step`.
+    pub fn prev(&mut self) {
+        let s0 = self.state0;
+        let s1 = self.state1 ^ s0 ^ (s0 >> 26);
+        self.state0 = unshift_left(unshift_right(s1, 17), 23);
+        self.state1 = s0;
+    }
+
+    /// `state0` as a double in `[0, 1)` the way V8 does it: the top 52 bits
+    /// become the mantissa of a number in `[1, 2)`, less one.
+    pub fn to_double(&self) -> f64 {
+        f64::from_bits(0x3FF0000000000000 | (self.state0 >> 12)) - 1.0
+    }
+}
+
+impl LinearRng for Xorshift128Plus {
+    fn state_len() -> usize {
+        128
+    }
+
+    fn from_state_bits(bits: &BitVec) -> Xorshift128Plus {
+        Xorshift128Plus {
+            state0: word_bits(bits, 0, 64),
+            state1: word_bits(bits, 64, 64),
+        }
+    }
+}
+
+impl SeedableRng<[u64; 2]> for Xorshift128Plus {
+    fn reseed(&mut self, seed: [u64; 2]) {
+        *self = Xorshift128Plus::new(seed[0], seed[1]);
+    }
+
+    fn from_seed(seed: [u64; 2]) -> Xorshift128Plus {
+        Xorshift128Plus::new(seed[0], seed[1])
+    }
+}
+
+impl Rng for Xorshift128Plus {
+    fn next_u32(&mut self) -> u32 {
+        (self.next_u64() >> 32) as u32
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.step();
+        self.state0.wrapping_add(self.state1)
+    }
+}
+
+const V8_CACHE_SIZE: usize = 64;
+
+/// V8's `Math.random()`: xorshift128+ fills a cache of 64 doubles at a time,
+/// which are handed out last first.
+#[derive(Clone, Debug)]
+pub struct V8MathRandom {
+    pub rng: Xorshift128Plus,
+    cache: Vec<f64>,
+}
+
+impl V8MathRandom {
+    /// The generator a context gets with `--random_seed=seed`; each state
+    /// word is seed through MurmurHash3's finalizer.
+    pub fn new(seed: u64) -> V8MathRandom {
+        V8MathRandom {
+            rng: Xorshift128Plus::new(murmur_hash3(seed), murmur_hash3(!seed)),
+            cache: Vec::with_capacity(V8_CACHE_SIZE),
+        }
+    }
+
+    pub fn next(&mut self) -> f64 {
+        if self.cache.is_empty() {
+            for _ in 0..V8_CACHE_SIZE {
+                self.rng.step();
+                self.cache.push(self.rng.to_double());
+            }
+        }
+        self.cache.pop().unwrap()
+    }
+
+    /// Clone `Math.random()` from its first few outputs after a cache
+    /// refill, such as the first ones a fresh context produces. Each double
+    /// gives away the top 52 bits of `state0`, but as each `state0` was the
+    /// previous `state1` they overlap, and it takes four to settle all 128
+    /// bits of state.
+    ///
+    /// The outputs come out of the cache backwards, so they're read off a
+    /// generator that steps back after each, starting from the state that
+    /// filled the last slot.
+    pub fn clone_from(outputs: &[f64]) -> Option<V8MathRandom> {
+        let n = outputs.len();
+        if n > V8_CACHE_SIZE || outputs.iter().any(|&x| !(x >= 0.0 && x < 1.0)) {
+            return None;
+        }
+        let observed = outputs.iter()
+            .map(|&x| ((x + 1.0).to_bits() << 12, !0 << 12))
+            .collect::<Vec<_>>();
+        let mut rng = match recover(&observed, |r: &mut Xorshift128Plus| {
+            let x = r.state0;
+            r.prev();
+            x
+        }) {
+            Some(rng) => rng,
+            None => return None,
+        };
+
+        // rng now holds the last slot left in the cache: go back to before
+        // the refill and fill it again, keeping the slots not yet handed out
+        for _ in 0..V8_CACHE_SIZE - n {
+            rng.prev();
+        }
+        let mut cache = Vec::with_capacity(V8_CACHE_SIZE);
+        for i in 0..V8_CACHE_SIZE {
+            rng.step();
+            if i < V8_CACHE_SIZE - n {
+                cache.push(rng.to_double());
+            }
+        }
+        Some(V8MathRandom {
+            rng: rng,
+            cache: cache,
+        })
+    }
+}
+
+fn murmur_hash3(mut h: u64) -> u64 {
+    h ^= h >> 33;
+    h = h.wrapping_mul(0xFF51AFD7ED558CCD);
+    h ^= h >> 33;
+    h = h.wrapping_mul(0xC4CEB9FE1A85EC53);
+    h ^= h >> 33;
+    h
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::File;
+    use std::io::{BufRead, BufReader};
+
+    use rand::{Rng, SeedableRng, XorShiftRng, thread_rng, weak_rng};
+
+    use super::{V8MathRandom, Xorshift128Plus, Xorshift32, Xorshift64, recover};
+
+    /// The `Math.random()` outputs in data/xorshift/v8.txt, by how node was
+    /// run.
+    fn read_v8() -> Vec<(String, Vec<f64>)> {
+        let f = File::open("./data/xorshift/v8.txt").unwrap();
+        let mut sections: Vec<(String, Vec<f64>)> = Vec::new();
+        for line in BufReader::new(f).lines() {
+            let line = line.unwrap();
+            if line.starts_with('#') {
+                continue;
+            }
+            match line.parse() {
+                Ok(x) => sections.last_mut().unwrap().1.push(x),
+                Err(_) => sections.push((line, Vec::new())),
+            }
+        }
+        sections
+    }
+
+    #[test]
+    fn test_v8_seeded() {
+        let data = read_v8();
+        assert_eq!("node --random_seed=42", data[0].0);
+        let mut rng = V8MathRandom::new(42);
+        for &x in &data[0].1 {
+            assert_eq!(x, rng.next());
+        }
+    }
+
+    #[test]
+    fn test_v8_clone() {
+        // past the end of the first cache, so into states none of the
+        // outputs came from
+        for (_, outputs) in read_v8() {
+            let mut rng = V8MathRandom::clone_from(&outputs[..4]).unwrap();
+            for &x in &outputs[4..] {
+                assert_eq!(x, rng.next());
+            }
+        }
+
+        let mut v8 = V8MathRandom::new(thread_rng().gen());
+        let x = v8.next();
+        assert!(V8MathRandom::clone_from(&[x]).is_none());
+    }
+
+    #[test]
+    fn test_xorshift128plus_prev() {
+        let mut rng = Xorshift128Plus::from_seed(thread_rng().gen());
+        let start = rng.clone();
+        for _ in 0..100 {
+            rng.step();
+        }
+        for _ in 0..100 {
+            rng.prev();
+        }
+        assert_eq!(start, rng);
+    }
+
+    #[test]
+    fn test_xorshift32_and_64() {
+        // one byte at a time
+        let mut rng = Xorshift32::new(thread_rng().gen_range(1, !0));
+        let bytes = rng.gen_iter::<u8>().take(6).collect::<Vec<_>>();
+        let observed = bytes.iter().map(|&b| (b as u64, 0xff)).collect::<Vec<_>>();
+        let mut cloned = recover::<Xorshift32, _>(&observed, |r| r.next() as u64).unwrap();
+        assert_eq!(rng, cloned);
+        assert_eq!(rng.next_u32(), cloned.next_u32());
+
+        // rand's next_f64 keeps the low 52 bits of next_u64, and it takes
+        // three to cover the state
+        let mut rng = Xorshift64::new(thread_rng().gen_range(1, !0));
+        let observed = (0..3).map(|_| {
+            let x = rng.next_f64();
+            ((x + 1.0).to_bits() & ((1 << 52) - 1), (1 << 52) - 1)
+        }).collect::<Vec<_>>();
+        let mut cloned = recover::<Xorshift64, _>(&observed, |r| r.next()).unwrap();
+        assert_eq!(rng.next_f64(), cloned.next_f64());
+    }
+
+    #[test]
+    fn test_weak_rng() {
+        // a key from fill_bytes, as in set 2, is the whole state
+        let mut rng = weak_rng();
+        let mut key = [0_u8; 16];
+        rng.fill_bytes(&mut key);
+        let observed = key.chunks(8)
+            .map(|c| (c.iter().rev().fold(0, |acc, &b| (acc << 8) | b as u64), !0))
+            .collect::<Vec<_>>();
+        let mut cloned = recover::<XorShiftRng, _>(&observed, |r| r.next_u64()).unwrap();
+        let mut next_key = [0_u8; 16];
+        rng.fill_bytes(&mut next_key);
+        let mut predicted = [0_u8; 16];
+        cloned.fill_bytes(&mut predicted);
+        assert_eq!(next_key, predicted);
+
+        // the low bits of rolls
+        let mut rng = weak_rng();
+        let rolls = (0..40).map(|_| rng.gen_range(0, 16_u32)).collect::<Vec<_>>();
+        let observed = rolls.iter().map(|&r| (r as u64, 0xf)).collect::<Vec<_>>();
+        let mut cloned = recover::<XorShiftRng, _>(&observed, |r| r.next_u32() as u64).unwrap();
+        for _ in 0..100 {
+            assert_eq!(rng.gen_range(0, 6_u32), cloned.gen_range(0, 6_u32));
+        }
+
+        // not enough
+        let observed = rolls[..20].iter().map(|&r| (r as u64, 0xf)).collect::<Vec<_>>();
+        assert!(recover::<XorShiftRng, _>(&observed, |r| r.next_u32() as u64).is_none());
+    }
+}